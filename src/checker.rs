@@ -0,0 +1,446 @@
+use crate::parser::{Node, Span};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// The type of an expression as far as it can be known before running the program.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Type {
+  Number,
+  Bool,
+  String,
+  Null,
+  //the type depends on values only known at runtime (e.g. function arguments)
+  Unknown,
+}
+
+impl Type {
+  // The type named by a type annotation node, Unknown when there is no annotation.
+  pub fn from_annotation(annotation: Option<&Node>) -> Type {
+    match annotation {
+      Some(Node::Type{name}) => match name.as_str() {
+        "int" => Type::Number,
+        "bool" => Type::Bool,
+        "string" => Type::String,
+        "null" => Type::Null,
+        _ => Type::Unknown,
+      },
+      _ => Type::Unknown,
+    }
+  }
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Type::Number => "int",
+      Type::Bool => "bool",
+      Type::String => "string",
+      Type::Null => "null",
+      Type::Unknown => "unknown",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeError {
+  //the function the error is in, empty for global initializers
+  pub function: String,
+  pub message: String,
+  //the name, call or statement the error is about
+  pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.function.is_empty() {
+      write!(f, "{}", self.message)
+    } else {
+      write!(f, "in {}: {}", self.function, self.message)
+    }
+  }
+}
+
+// What the checker found out about the types of a program, e.g. for an editor to show.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Types {
+  //the return type of each function
+  pub functions: HashMap<String, Type>,
+  //lets, consts and parameters by the function they are in ("" for globals) and their name,
+  //a name defined more than once in a function has the type of the last definition
+  pub variables: HashMap<(String, String), Type>,
+}
+
+// Combines the types of two values that may end up in the same place (e.g. the branches of an if).
+//Returns None when they can not be combined.
+fn join(a: Type, b: Type) -> Option<Type> {
+  match (a, b) {
+    _ if a == b => Some(a),
+    //null mixed with another type, or anything mixed with unknown, can only be known at runtime
+    (Type::Unknown, _) | (_, Type::Unknown) | (Type::Null, _) | (_, Type::Null) => Some(Type::Unknown),
+    _ => None,
+  }
+}
+
+// Return types of the functions the interpreter provides.
+fn builtin_type(name: &str) -> Option<Type> {
+  match name {
+    "is_null" => Some(Type::Bool),
+    "assert" | "print" | "println" => Some(Type::Null),
+    "format" => Some(Type::String),
+    _ => None,
+  }
+}
+
+struct Checker<'a> {
+  //function definitions by name
+  definitions: HashMap<String, &'a Node>,
+  //inferred return types of the functions checked so far
+  returns: HashMap<String, Type>,
+  //functions whose body is being checked, a call to one of these is recursive
+  in_progress: HashSet<String>,
+
+  globals: HashMap<String, Type>,
+  //scopes of the function being checked, an if block gets its own scope like at runtime
+  scopes: Vec<HashMap<String, Type>>,
+  function: String,
+  //the statement being checked, errors without a closer position are reported at it
+  span: Span,
+  //the type of every binding checked, see Types
+  variables: HashMap<(String, String), Type>,
+
+  errors: Vec<TypeError>,
+}
+
+impl<'a> Checker<'a> {
+
+  fn error(&mut self, span: Span, message: String) {
+    self.errors.push(TypeError{function: self.function.clone(), message, span});
+  }
+
+  fn lookup(&self, name: &str) -> Type {
+    match self.scopes.last().and_then(|scope| scope.get(name)) {
+      Some(t) => *t,
+      None => *self.globals.get(name).unwrap_or(&Type::Unknown),
+    }
+  }
+
+  fn define(&mut self, name: &str, t: Type) {
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.insert(name.to_string(), t);
+    }
+    self.variables.insert((self.function.clone(), name.to_string()), t);
+  }
+
+  // Reports a value whose type is known and differs from the annotation, returns the type the value is known to have.
+  fn annotated(&mut self, t: Type, annotation: Option<&Node>, what: String, span: Span) -> Type {
+    let declared = Type::from_annotation(annotation);
+    if declared == Type::Unknown {
+      return t;
+    }
+    if t != Type::Unknown && t != declared {
+      self.error(span, format!("{} is {}, declared {}", what, t, declared));
+    }
+    declared
+  }
+
+  // The declared return type and parameter types of a function.
+  fn signature(&self, name: &str) -> (Type, Vec<Type>) {
+    let mut returns = Type::Unknown;
+    let mut params = Vec::new();
+    if let Some(Node::FunctionDefine{children, ..}) = self.definitions.get(name) {
+      for child in children {
+        match child {
+          Node::Type{..} => returns = Type::from_annotation(Some(child)),
+          Node::FunctionArguments{children} => {
+            for param in children {
+              if let Node::Parameter{children} = param {
+                params.push(Type::from_annotation(children.get(1)));
+              }
+            }
+          },
+          _ => (),
+        }
+      }
+    }
+    (returns, params)
+  }
+
+  // Checks the body of a function once and returns its return type.
+  fn function_type(&mut self, name: &str) -> Type {
+    if let Some(t) = self.returns.get(name) {
+      return *t;
+    }
+    let definition = match self.definitions.get(name) {
+      Some(definition) => *definition,
+      None => return builtin_type(name).unwrap_or(Type::Unknown),
+    };
+    //the type of a recursive call is not known until the whole body has been checked, unless it is declared
+    let (declared, _) = self.signature(name);
+    if self.in_progress.contains(name) {
+      return declared;
+    }
+
+    self.in_progress.insert(name.to_string());
+    let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+    let function = std::mem::replace(&mut self.function, name.to_string());
+    let span = self.span;
+
+    let mut t = Type::Null;
+    if let Node::FunctionDefine{children, ..} = definition {
+      for child in &children[1..] {
+        if let Node::FunctionArguments{children} = child {
+          for param in children {
+            if let Node::Parameter{children} = param {
+              if let Node::Identifier{value, ..} = &children[0] {
+                self.define(value, Type::from_annotation(children.get(1)));
+              }
+            }
+          }
+        }
+      }
+      t = self.statements(&children[1..]);
+    }
+
+    //a return value that does not match is reported at the name of the function
+    let t = match definition {
+      Node::FunctionDefine{children, ..} => {
+        let at = children[0].span().unwrap_or_default();
+        self.annotated(t, children.iter().find(|child| matches!(child, Node::Type{..})), "return value".to_string(), at)
+      },
+      _ => t,
+    };
+
+    self.scopes = scopes;
+    self.function = function;
+    self.span = span;
+    self.in_progress.remove(name);
+
+    self.returns.insert(name.to_string(), t);
+    t
+  }
+
+  // Checks a statement, returns the type of the returned value if it is a return.
+  fn statement(&mut self, node: &Node) -> Option<Type> {
+    match node {
+      Node::VariableDefine{children} | Node::ConstantDefine{children} => {
+        let t = self.expression(&children[1]);
+        if let Node::Identifier{value, span} = &children[0] {
+          let t = self.annotated(t, children.get(2), value.to_string(), *span);
+          self.define(value, t);
+        }
+        None
+      },
+      Node::FunctionReturn{children} => Some(self.expression(&children[0])),
+      Node::IfExpression{..} | Node::ExpressionStatement{..} => {
+        self.expression(node);
+        None
+      },
+      _ => None,
+    }
+  }
+
+  // Checks the statements of a function or an if block up to the first return, returns the type of the value they give.
+  //An if statement whose blocks return may end them early, so its type is joined in unless it is null.
+  fn statements(&mut self, nodes: &[Node]) -> Type {
+    let mut early: Option<Type> = None;
+    let mut result = Type::Null;
+    let outer = self.span;
+    for node in nodes {
+      if let Node::Statement{children, span} = node {
+        self.span = *span;
+        if let Node::IfExpression{..} = &children[0] {
+          let t = self.expression(&children[0]);
+          if t != Type::Null {
+            early = Some(early.map(|early| join(early, t).unwrap_or(Type::Unknown)).unwrap_or(t));
+          }
+        } else if let Some(t) = self.statement(&children[0]) {
+          result = t;
+          //the statements after a return are never run
+          break;
+        }
+      }
+    }
+    self.span = outer;
+    match early {
+      Some(early) => join(early, result).unwrap_or(Type::Unknown),
+      None => result,
+    }
+  }
+
+  // Checks the statements of an if block in a new scope, returns the value of the block.
+  fn block(&mut self, node: &Node) -> Type {
+    let scope = self.scopes.last().cloned().unwrap_or_default();
+    self.scopes.push(scope);
+    let result = match node {
+      Node::IfStatements{children} => self.statements(children),
+      _ => Type::Null,
+    };
+    self.scopes.pop();
+    result
+  }
+
+  fn condition(&mut self, node: &Node) {
+    let t = self.expression(node);
+    if t != Type::Bool && t != Type::Unknown {
+      self.error(self.span, format!("if condition is {}, not {}", t, Type::Bool));
+    }
+  }
+
+  fn expression(&mut self, node: &Node) -> Type {
+    match node {
+      Node::Expression{children} | Node::ExpressionStatement{children} => self.expression(&children[0]),
+      Node::Number{..} => Type::Number,
+      Node::Bool{..} => Type::Bool,
+      Node::String{..} => Type::String,
+      Node::Null => Type::Null,
+      Node::Identifier{value, ..} => self.lookup(value),
+      Node::MathExpression{name, children} => {
+        let lhs = self.expression(&children[0]);
+        let rhs = self.expression(&children[1]);
+        let numeric = |t: Type| t == Type::Number || t == Type::Unknown;
+        if !numeric(lhs) || !numeric(rhs) {
+          self.error(self.span, format!("cannot apply {} to {} and {}", name, lhs, rhs));
+        }
+        Type::Number
+      },
+      Node::ConditionalExpression{name, children} => {
+        let lhs = self.expression(&children[0]);
+        let rhs = self.expression(&children[1]);
+        let possible = match (lhs, rhs) {
+          (Type::Unknown, _) | (_, Type::Unknown) => true,
+          (Type::Number, Type::Number) => true,
+          (Type::Bool, Type::Bool) | (Type::Null, _) | (_, Type::Null) => name == "==" || name == "!=",
+          _ => false,
+        };
+        if !possible {
+          self.error(self.span, format!("cannot compare {} with {} using {}", lhs, rhs, name));
+        }
+        Type::Bool
+      },
+      Node::CoalesceExpression{children} => {
+        let lhs = self.expression(&children[0]);
+        let rhs = self.expression(&children[1]);
+        match lhs {
+          Type::Null => rhs,
+          Type::Unknown => join(lhs, rhs).unwrap_or(Type::Unknown),
+          //the right side is never used
+          _ => lhs,
+        }
+      },
+      Node::IfExpression{children} => {
+        self.condition(&children[0]);
+        let mut branches = vec![self.block(&children[1])];
+        let mut has_else = false;
+        for child in &children[2..] {
+          match child {
+            Node::ElseIfExpression{children} => {
+              self.condition(&children[0]);
+              branches.push(self.block(&children[1]));
+            },
+            Node::ElseExpression{children} => {
+              has_else = true;
+              branches.push(self.block(&children[0]));
+            },
+            _ => (),
+          }
+        }
+        //without an else the if is null when no branch is taken
+        if !has_else {
+          branches.push(Type::Null);
+        }
+
+        let mut result = branches[0];
+        for t in &branches[1..] {
+          result = match join(result, *t) {
+            Some(joined) => joined,
+            None => {
+              self.error(self.span, format!("if branches have different types: {} and {}", result, t));
+              Type::Unknown
+            },
+          };
+        }
+        result
+      },
+      Node::FunctionCall{name, children, span} => {
+        let (_, params) = self.signature(name);
+        for args in children {
+          if let Node::FunctionArguments{children} = args {
+            for (index, arg) in children.iter().enumerate() {
+              let t = self.expression(arg);
+              let declared = *params.get(index).unwrap_or(&Type::Unknown);
+              if t != Type::Unknown && declared != Type::Unknown && t != declared {
+                self.error(*span, format!("argument {} of {} is {}, declared {}", index + 1, name, t, declared));
+              }
+            }
+          }
+        }
+        self.function_type(name)
+      },
+      _ => Type::Unknown,
+    }
+  }
+}
+
+//global initializers are checked in order, then every function
+fn run(program: &Node) -> Checker<'_> {
+  let mut checker = Checker {
+    definitions: HashMap::new(),
+    returns: HashMap::new(),
+    in_progress: HashSet::new(),
+    globals: HashMap::new(),
+    scopes: Vec::new(),
+    function: String::new(),
+    span: Span::default(),
+    variables: HashMap::new(),
+    errors: Vec::new(),
+  };
+
+  let children = match program {
+    Node::Program{children} => children,
+    _ => return checker,
+  };
+
+  for child in children {
+    if let Node::FunctionDefine{children, ..} = child {
+      if let Node::Identifier{value, ..} = &children[0] {
+        checker.definitions.insert(value.to_string(), child);
+      }
+    }
+  }
+
+  for child in children {
+    if let Node::Statement{children, span} = child {
+      if let Node::VariableDefine{children} | Node::ConstantDefine{children} = &children[0] {
+        checker.scopes = vec![HashMap::new()];
+        checker.span = *span;
+        let t = checker.expression(&children[1]);
+        if let Node::Identifier{value, span} = &children[0] {
+          let t = checker.annotated(t, children.get(2), value.to_string(), *span);
+          checker.globals.insert(value.to_string(), t);
+          checker.variables.insert((String::new(), value.to_string()), t);
+        }
+      }
+    }
+  }
+
+  for child in children {
+    if let Node::FunctionDefine{children, ..} = child {
+      if let Node::Identifier{value, ..} = &children[0] {
+        checker.function_type(value);
+      }
+    }
+  }
+  checker
+}
+
+// Checks the types of a parsed program without running it, each type error found is returned.
+pub fn check(program: &Node) -> Vec<TypeError> {
+  run(program).errors
+}
+
+// The types of the functions and variables of a parsed program, as far as they can be known without running it.
+pub fn infer(program: &Node) -> Types {
+  let checker = run(program);
+  Types{functions: checker.returns, variables: checker.variables}
+}
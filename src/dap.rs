@@ -0,0 +1,344 @@
+use crate::debugger::{Resume, Stepper};
+use crate::interpreter::{Debugger, Output, Runtime, Value, INTERRUPTED};
+use crate::parser::Span;
+use crate::protocol;
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// The id of the only thread, a script runs on one thread.
+const THREAD: i64 = 1;
+
+// The variablesReference of the two scopes of a frame, variables themselves have no children.
+const LOCALS: i64 = 1;
+const GLOBALS: i64 = 2;
+
+// Reads and writes the messages of the protocol, numbering the ones it sends.
+struct Connection {
+  input: Box<dyn BufRead>,
+  output: Box<dyn Write>,
+  //the seq of the next message sent
+  seq: i64,
+}
+
+impl Connection {
+
+  fn read(&mut self) -> Option<Json> {
+    protocol::read(&mut self.input)
+  }
+
+  fn send(&mut self, mut message: Json) {
+    message["seq"] = json!(self.seq);
+    self.seq += 1;
+    protocol::write(&mut self.output, &message);
+  }
+
+  fn respond(&mut self, request: &Json, body: Json) {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request["seq"],
+      "command": request["command"],
+      "success": true,
+      "body": body,
+    }));
+  }
+
+  fn fail(&mut self, request: &Json, message: &str) {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request["seq"],
+      "command": request["command"],
+      "success": false,
+      "message": message,
+    }));
+  }
+
+  fn event(&mut self, event: &str, body: Json) {
+    self.send(json!({"type": "event", "event": event, "body": body}));
+  }
+}
+
+// What a request asks of the session beyond its response.
+enum Flow {
+  //the client is done configuring, the script can start
+  Run,
+  //a paused script goes on
+  Resume(Resume),
+  Disconnect,
+}
+
+// The state of a debugging session, shared by the server, the debugger in the runtime and the script's output.
+struct Session {
+  connection: Connection,
+  stepper: Stepper,
+  //the script being debugged and its source, which the lines of breakpoints and frames are in
+  program: Option<PathBuf>,
+  source: String,
+  //the line of the statement each call is at, outermost first
+  lines: Vec<usize>,
+  //false until the script has paused once, the first pause of stopOnEntry is reported as an entry
+  paused: bool,
+}
+
+//true if path names the file of program, the client may spell the path differently
+fn same_file(program: &Path, path: &str) -> bool {
+  match (std::fs::canonicalize(program), std::fs::canonicalize(path)) {
+    (Ok(program), Ok(path)) => program == path,
+    _ => program == Path::new(path),
+  }
+}
+
+//the name of the type of a value, as in type annotations
+fn type_name(value: &Value) -> &'static str {
+  match value {
+    Value::Number(_) => "int",
+    Value::Bool(_) => "bool",
+    Value::String(_) => "string",
+    Value::Null => "null",
+  }
+}
+
+//the variables of a scope in the form of a variables response, sorted by name
+fn variables(scope: Option<&HashMap<String, Value>>) -> Json {
+  let mut variables: Vec<(&String, &Value)> = scope.map(|scope| scope.iter().collect()).unwrap_or_default();
+  variables.sort_by(|a, b| a.0.cmp(b.0));
+  let variables: Vec<Json> = variables.iter().map(|(name, value)| {
+    let shown = match value {
+      Value::String(value) => format!("{:?}", value),
+      value => value.to_string(),
+    };
+    json!({"name": name, "value": shown, "type": type_name(value), "variablesReference": 0})
+  }).collect();
+  json!({"variables": variables})
+}
+
+impl Session {
+
+  //answers a request, runtime is the paused script if there is one
+  fn handle(&mut self, request: &Json, runtime: Option<&Runtime>) -> Option<Flow> {
+    let arguments = &request["arguments"];
+    match request["command"].as_str().unwrap_or("") {
+      "initialize" => {
+        self.connection.respond(request, json!({"supportsConfigurationDoneRequest": true}));
+        self.connection.event("initialized", json!({}));
+      },
+      "launch" => {
+        let program = match arguments["program"].as_str() {
+          Some(program) => PathBuf::from(program),
+          None => {
+            self.connection.fail(request, "launch needs a program");
+            return None;
+          },
+        };
+        self.source = match std::fs::read_to_string(&program) {
+          Ok(source) => source,
+          Err(_) => {
+            self.connection.fail(request, &format!("could not read {}", program.display()));
+            return None;
+          },
+        };
+        self.program = Some(program);
+        if !arguments["stopOnEntry"].as_bool().unwrap_or(false) {
+          self.stepper.resume(Resume::Continue, 0);
+        }
+        self.connection.respond(request, json!({}));
+      },
+      //the breakpoints of a source replace the ones set before, only the program itself can have breakpoints,
+      //the ones of other sources, like imported modules, are answered as not verified
+      "setBreakpoints" => {
+        let path = arguments["source"]["path"].as_str().unwrap_or("");
+        let verified = self.program.as_ref().map(|program| same_file(program, path)).unwrap_or(false);
+        if verified {
+          self.stepper.clear_breakpoints();
+        }
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+          if let Some(line) = breakpoint["line"].as_u64() {
+            if verified {
+              self.stepper.add_breakpoint(line as usize);
+              breakpoints.push(json!({"verified": true, "line": line}));
+            } else {
+              breakpoints.push(json!({"verified": false, "line": line, "message": "breakpoints can only be set in the launched program"}));
+            }
+          }
+        }
+        self.connection.respond(request, json!({"breakpoints": breakpoints}));
+      },
+      "configurationDone" => {
+        self.connection.respond(request, json!({}));
+        return Some(Flow::Run);
+      },
+      "threads" => self.connection.respond(request, json!({"threads": [{"id": THREAD, "name": "main"}]})),
+      "stackTrace" => {
+        let calls = runtime.map(|runtime| runtime.backtrace()).unwrap_or(&[]);
+        let source = json!({"path": self.program});
+        let frames: Vec<Json> = calls.iter().enumerate().rev().map(|(index, call)| json!({
+          "id": calls.len() - 1 - index,
+          "name": call,
+          "source": source,
+          "line": self.lines.get(index).copied().unwrap_or(0),
+          "column": 1,
+        })).collect();
+        self.connection.respond(request, json!({"stackFrames": frames, "totalFrames": calls.len()}));
+      },
+      //only the innermost frame has its variables, the frames of the calls further out are not kept apart
+      "scopes" => {
+        let mut scopes = vec![json!({"name": "Globals", "variablesReference": GLOBALS, "expensive": false})];
+        if arguments["frameId"].as_i64() == Some(0) {
+          scopes.insert(0, json!({"name": "Locals", "variablesReference": LOCALS, "expensive": false}));
+        }
+        self.connection.respond(request, json!({"scopes": scopes}));
+      },
+      "variables" => {
+        let body = match arguments["variablesReference"].as_i64() {
+          Some(LOCALS) => variables(runtime.and_then(|runtime| runtime.frame())),
+          Some(GLOBALS) => variables(runtime.map(|runtime| &runtime.globals)),
+          _ => variables(None),
+        };
+        self.connection.respond(request, body);
+      },
+      "continue" | "next" | "stepIn" | "stepOut" if runtime.is_none() => self.connection.fail(request, "the script is not paused"),
+      "continue" => {
+        self.connection.respond(request, json!({"allThreadsContinued": true}));
+        return Some(Flow::Resume(Resume::Continue));
+      },
+      "next" => {
+        self.connection.respond(request, json!({}));
+        return Some(Flow::Resume(Resume::Next));
+      },
+      "stepIn" => {
+        self.connection.respond(request, json!({}));
+        return Some(Flow::Resume(Resume::Step));
+      },
+      "stepOut" => {
+        self.connection.respond(request, json!({}));
+        return Some(Flow::Resume(Resume::Finish));
+      },
+      "disconnect" => {
+        self.connection.respond(request, json!({}));
+        return Some(Flow::Disconnect);
+      },
+      command => self.connection.fail(request, &format!("{} is not supported", command)),
+    }
+    None
+  }
+}
+
+// Pauses the script where the stepper says and answers requests until the client lets it go on.
+struct Adapter {
+  session: Rc<RefCell<Session>>,
+}
+
+impl Debugger for Adapter {
+  fn before(&mut self, runtime: &mut Runtime, span: Span) -> Result<(), &'static str> {
+    let mut session = self.session.borrow_mut();
+    let (line, _) = span.line_col(&session.source);
+    let depth = runtime.backtrace().len();
+    session.lines.resize(depth, 0);
+    if let Some(last) = session.lines.last_mut() {
+      *last = line;
+    }
+    if !session.stepper.should_pause(line, depth) {
+      return Ok(());
+    }
+
+    let reason = if session.stepper.breakpoints().any(|breakpoint| *breakpoint == line) {
+      "breakpoint"
+    } else if session.paused {
+      "step"
+    } else {
+      "entry"
+    };
+    session.paused = true;
+    session.connection.event("stopped", json!({"reason": reason, "threadId": THREAD, "allThreadsStopped": true}));
+    loop {
+      //a client that goes away while the script is paused stops it
+      let request = match session.connection.read() {
+        Some(request) => request,
+        None => return Err(INTERRUPTED),
+      };
+      match session.handle(&request, Some(runtime)) {
+        Some(Flow::Resume(resume)) => {
+          session.stepper.resume(resume, depth);
+          return Ok(());
+        },
+        Some(Flow::Disconnect) => return Err(INTERRUPTED),
+        _ => (),
+      }
+    }
+  }
+}
+
+// Sends what the script prints to the client as output events.
+struct Console {
+  session: Rc<RefCell<Session>>,
+}
+
+impl Write for Console {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    let text = String::from_utf8_lossy(buffer);
+    self.session.borrow_mut().connection.event("output", json!({"category": "stdout", "output": text}));
+    Ok(buffer.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+//runs the launched program under the adapter, returns false if the client disconnected while it ran
+fn run(session: &Rc<RefCell<Session>>) -> bool {
+  let program = match session.borrow().program.clone() {
+    Some(program) => program,
+    None => {
+      session.borrow_mut().connection.event("terminated", json!({}));
+      return true;
+    },
+  };
+  let mut runtime = Runtime::new();
+  runtime.set_output(Output::Writer(Box::new(Console{session: Rc::clone(session)})));
+  runtime.set_debugger(Some(Box::new(Adapter{session: Rc::clone(session)})));
+  let result = runtime.load_file(&program).and_then(|_| runtime.run_main().map_err(|error| runtime.runtime_error(error)));
+
+  let mut session = session.borrow_mut();
+  let (category, output, code) = match result {
+    Ok(value) => ("console", format!("{:?}\n", value), 0),
+    Err(error) if error.message() == INTERRUPTED => return false,
+    Err(error) => ("stderr", format!("ERROR {}\n", error), 1),
+  };
+  session.connection.event("output", json!({"category": category, "output": output}));
+  session.connection.event("exited", json!({"exitCode": code}));
+  session.connection.event("terminated", json!({}));
+  true
+}
+
+// Serves the Debug Adapter Protocol on input and output until the client disconnects, as asalang dap does on stdio.
+//The client launches a program with {"program": path, "stopOnEntry": bool}, which runs on the tree walker once it
+//sends configurationDone. Breakpoints, stepping, the call stack and the variables of the innermost call and the
+//globals are supported, statements of imported modules are run without stopping.
+pub fn serve(input: Box<dyn BufRead>, output: Box<dyn Write>) {
+  let session = Rc::new(RefCell::new(Session {
+    connection: Connection{input, output, seq: 1},
+    stepper: Stepper::new(),
+    program: None,
+    source: String::new(),
+    lines: Vec::new(),
+    paused: false,
+  }));
+
+  loop {
+    let request = match session.borrow_mut().connection.read() {
+      Some(request) => request,
+      None => return,
+    };
+    let flow = session.borrow_mut().handle(&request, None);
+    match flow {
+      Some(Flow::Run) if !run(&session) => return,
+      Some(Flow::Disconnect) => return,
+      _ => (),
+    }
+  }
+}
@@ -0,0 +1,193 @@
+use crate::interpreter::{Debugger, Runtime, Value, INTERRUPTED};
+use crate::parser::Span;
+use std::collections::BTreeSet;
+use std::io::BufRead;
+
+// How far the script runs before it is paused again.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Resume {
+  //until a breakpoint
+  Continue,
+  //to the next statement, in whatever call it is
+  Step,
+  //to the next statement of the current call, calls it makes run through
+  Next,
+  //until the current call has returned
+  Finish,
+}
+
+// Decides at which statements to pause: at breakpoints, and where a step, next or finish ends.
+//Depths are the number of calls being run, Runtime::backtrace().len().
+#[derive(Debug)]
+pub struct Stepper {
+  //lines of the root program
+  breakpoints: BTreeSet<usize>,
+  resume: Resume,
+  //the depth the script was paused at when it was resumed
+  depth: usize,
+}
+
+impl Stepper {
+
+  // A stepper that pauses at the first statement.
+  pub fn new() -> Stepper {
+    Stepper {
+      breakpoints: BTreeSet::new(),
+      resume: Resume::Step,
+      depth: 0,
+    }
+  }
+
+  pub fn add_breakpoint(&mut self, line: usize) {
+    self.breakpoints.insert(line);
+  }
+
+  // Returns false if there was no breakpoint at the line.
+  pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+    self.breakpoints.remove(&line)
+  }
+
+  pub fn clear_breakpoints(&mut self) {
+    self.breakpoints.clear();
+  }
+
+  pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+    self.breakpoints.iter()
+  }
+
+  // Lets the script go on from a pause at depth.
+  pub fn resume(&mut self, resume: Resume, depth: usize) {
+    self.resume = resume;
+    self.depth = depth;
+  }
+
+  // True if the statement at line, run at depth, is where the script has to pause.
+  pub fn should_pause(&self, line: usize, depth: usize) -> bool {
+    self.breakpoints.contains(&line) || match self.resume {
+      Resume::Continue => false,
+      Resume::Step => true,
+      Resume::Next => depth <= self.depth,
+      Resume::Finish => depth < self.depth,
+    }
+  }
+}
+
+impl Default for Stepper {
+  fn default() -> Stepper {
+    Stepper::new()
+  }
+}
+
+const HELP: &str = "commands:
+  break LINE (b)     pause before the statements on LINE
+  delete LINE (d)    remove the breakpoint on LINE
+  step (s)           run to the next statement
+  next (n)           run to the next statement of this call
+  finish (f)         run until this call returns
+  continue (c)       run to the next breakpoint
+  frame (p)          show the variables of this call
+  backtrace (bt)     show the calls being run
+  quit (q)           stop the script
+";
+
+// The debugger of asalang debug, it reads commands from input and writes to the output of the runtime.
+//It pauses at the first statement of main so breakpoints can be set before anything runs. When input
+//ends the script runs to its end.
+pub struct Console {
+  stepper: Stepper,
+  //the root program, breakpoints and the statements shown are lines of it
+  source: String,
+  input: Box<dyn BufRead>,
+}
+
+impl Console {
+
+  pub fn new(source: &str, input: Box<dyn BufRead>) -> Console {
+    Console {
+      stepper: Stepper::new(),
+      source: source.to_string(),
+      input,
+    }
+  }
+
+  //a line of the source with surrounding whitespace removed
+  fn line(&self, line: usize) -> &str {
+    self.source.lines().nth(line - 1).unwrap_or("").trim()
+  }
+
+  //runs one command, returns how to resume if it lets the script go on
+  fn command(&mut self, runtime: &mut Runtime, command: &str) -> Result<Option<Resume>, &'static str> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words.as_slice() {
+      ["step"] | ["s"] => Ok(Some(Resume::Step)),
+      ["next"] | ["n"] => Ok(Some(Resume::Next)),
+      ["finish"] | ["f"] => Ok(Some(Resume::Finish)),
+      ["continue"] | ["c"] => Ok(Some(Resume::Continue)),
+      ["quit"] | ["q"] => Err(INTERRUPTED),
+      ["break", line] | ["b", line] | ["delete", line] | ["d", line] => {
+        let line = match line.parse::<usize>() {
+          Ok(line) if line > 0 => line,
+          _ => {
+            runtime.write(&format!("not a line: {}\n", line))?;
+            return Ok(None);
+          },
+        };
+        if words[0].starts_with('b') {
+          self.stepper.add_breakpoint(line);
+          runtime.write(&format!("breakpoint at line {}\n", line))?;
+        } else if self.stepper.remove_breakpoint(line) {
+          runtime.write(&format!("deleted breakpoint at line {}\n", line))?;
+        } else {
+          runtime.write(&format!("no breakpoint at line {}\n", line))?;
+        }
+        Ok(None)
+      },
+      ["frame"] | ["p"] => {
+        let mut variables: Vec<(&String, &Value)> = runtime.frame().map(|frame| frame.iter().collect()).unwrap_or_default();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        let text: String = variables.iter().map(|(name, value)| format!("{} = {:?}\n", name, value)).collect();
+        runtime.write(&text)?;
+        Ok(None)
+      },
+      ["backtrace"] | ["bt"] => {
+        let text: String = runtime.backtrace().iter().rev().enumerate().map(|(index, call)| format!("#{} {}\n", index, call)).collect();
+        runtime.write(&text)?;
+        Ok(None)
+      },
+      [] => Ok(None),
+      _ => {
+        runtime.write(HELP)?;
+        Ok(None)
+      },
+    }
+  }
+}
+
+impl Debugger for Console {
+  fn before(&mut self, runtime: &mut Runtime, span: Span) -> Result<(), &'static str> {
+    let (line, _) = span.line_col(&self.source);
+    let depth = runtime.backtrace().len();
+    if !self.stepper.should_pause(line, depth) {
+      return Ok(());
+    }
+
+    let call = runtime.backtrace().last().cloned().unwrap_or_default();
+    runtime.write(&format!("{}:{} {}\n", call, line, self.line(line)))?;
+    loop {
+      runtime.write("(asa) ")?;
+      let mut command = String::new();
+      let resume = match self.input.read_line(&mut command) {
+        Ok(0) | Err(_) => {
+          self.stepper.clear_breakpoints();
+          Resume::Continue
+        },
+        Ok(_) => match self.command(runtime, &command)? {
+          Some(resume) => resume,
+          None => continue,
+        },
+      };
+      self.stepper.resume(resume, depth);
+      return Ok(());
+    }
+  }
+}
@@ -0,0 +1,296 @@
+use crate::parser::{comment, program, string, Node};
+
+const INDENT: &str = "  ";
+
+// How tightly an expression binds, an operand with a lower level than its place needs parentheses.
+//The levels follow the parser: ?? then comparisons then l1 (+ -), l2 (* /), l3 (^) and l4 (literals, names, calls).
+fn level(node: &Node) -> u8 {
+  match node {
+    Node::Expression{children} => level(&children[0]),
+    Node::IfExpression{..} => 0,
+    Node::CoalesceExpression{..} => 1,
+    Node::ConditionalExpression{..} => 2,
+    Node::MathExpression{name, ..} => match name.as_str() {
+      "+" | "-" => 3,
+      "*" | "/" => 4,
+      _ => 5,
+    },
+    //negative numbers can only be written as a subtraction
+    Node::Number{value} if *value < 0 => 3,
+    _ => 6,
+  }
+}
+
+fn pad(depth: usize) -> String {
+  INDENT.repeat(depth)
+}
+
+fn escape(text: &str) -> String {
+  let mut result = String::new();
+  for c in text.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\t' => result.push_str("\\t"),
+      c => result.push(c),
+    }
+  }
+  result
+}
+
+// Formats an operand, in parentheses if it binds less tightly than min.
+//depth is the indentation of the line the expression starts on, an if expression spans several lines.
+fn operand(node: &Node, min: u8, depth: usize) -> String {
+  let text = expression(node, depth);
+  if level(node) < min {
+    format!("({})", text)
+  } else {
+    text
+  }
+}
+
+fn expression(node: &Node, depth: usize) -> String {
+  match node {
+    Node::Expression{children} => expression(&children[0], depth),
+    Node::Number{value} if *value < 0 => format!("0 - {}", -(*value as i64)),
+    Node::Number{value} => value.to_string(),
+    Node::Bool{value} => value.to_string(),
+    Node::String{value} => format!("\"{}\"", escape(value)),
+    Node::Null => "null".to_string(),
+    Node::Identifier{value, ..} => value.to_string(),
+    Node::FunctionCall{name, children, ..} => {
+      let args: Vec<String> = children.iter()
+        .flat_map(|args| args.children())
+        .map(|arg| expression(arg, depth))
+        .collect();
+      format!("{}({})", name, args.join(", "))
+    },
+    Node::CoalesceExpression{children} => {
+      format!("{} ?? {}", operand(&children[0], 1, depth), operand(&children[1], 2, depth))
+    },
+    //comparison chains group to the left, the right side is a math expression
+    Node::ConditionalExpression{name, children} => {
+      format!("{} {} {}", operand(&children[0], 2, depth), name, operand(&children[1], 3, depth))
+    },
+    Node::MathExpression{name, children} => {
+      //+ - and ^ group to the left, * and / group to the right
+      let (left, right) = match name.as_str() {
+        "+" | "-" => (3, 4),
+        "*" | "/" => (5, 4),
+        _ => (5, 6),
+      };
+      format!("{} {} {}", operand(&children[0], left, depth), name, operand(&children[1], right, depth))
+    },
+    Node::IfExpression{children} => {
+      //the condition is parsed as a comparison, so ?? needs parentheses there
+      let mut text = format!("if {} {}", operand(&children[0], 2, depth), braces(&children[1], depth));
+      for child in &children[2..] {
+        match child {
+          Node::ElseIfExpression{children} => {
+            text.push_str(&format!(" else if {} {}", operand(&children[0], 2, depth), braces(&children[1], depth)));
+          },
+          Node::ElseExpression{children} => {
+            text.push_str(&format!(" else {}", braces(&children[0], depth)));
+          },
+          _ => (),
+        }
+      }
+      text
+    },
+    _ => String::new(),
+  }
+}
+
+fn braces(node: &Node, depth: usize) -> String {
+  let children = node.children();
+  if children.is_empty() {
+    return "{}".to_string();
+  }
+  let mut text = String::from("{\n");
+  block(&mut text, children, depth + 1);
+  text.push_str(&pad(depth));
+  text.push('}');
+  text
+}
+
+fn annotation(children: &[Node]) -> String {
+  match children.iter().find(|child| matches!(child, Node::Type{..})) {
+    Some(Node::Type{name}) => format!(": {}", name),
+    _ => String::new(),
+  }
+}
+
+fn statement(node: &Node, depth: usize) -> String {
+  match node {
+    Node::VariableDefine{children} => {
+      format!("let {}{} = {};", expression(&children[0], depth), annotation(&children[2..]), expression(&children[1], depth))
+    },
+    Node::ConstantDefine{children} => {
+      format!("const {}{} = {};", expression(&children[0], depth), annotation(&children[2..]), expression(&children[1], depth))
+    },
+    Node::FunctionReturn{children} => format!("return {};", expression(&children[0], depth)),
+    Node::ExpressionStatement{children} => format!("{};", expression(&children[0], depth)),
+    //an if statement ends with its closing brace
+    Node::IfExpression{..} => expression(node, depth),
+    _ => String::new(),
+  }
+}
+
+//adds a comment to the end of the last line
+fn trailing(out: &mut String, comment: &str) {
+  if out.ends_with('\n') {
+    out.pop();
+  }
+  out.push(' ');
+  out.push_str(comment);
+  out.push('\n');
+}
+
+//adds the statements and comments of a function body or if block, one per line
+fn block(out: &mut String, children: &[Node], depth: usize) {
+  for child in children {
+    match child {
+      Node::Comment{value, trailing: true} => trailing(out, value),
+      Node::Comment{value, ..} => out.push_str(&format!("{}{}\n", pad(depth), value)),
+      Node::Statement{children, ..} => out.push_str(&format!("{}{}\n", pad(depth), statement(&children[0], depth))),
+      _ => (),
+    }
+  }
+}
+
+fn function(out: &mut String, children: &[Node]) {
+  let mut params = Vec::new();
+  for child in &children[1..] {
+    if let Node::FunctionArguments{children} = child {
+      for param in children {
+        params.push(param.to_source());
+      }
+    }
+  }
+  let returns = match children.iter().find(|child| matches!(child, Node::Type{..})) {
+    Some(Node::Type{name}) => format!(" -> {}", name),
+    _ => String::new(),
+  };
+  out.push_str(&format!("fn {}({}){} {{\n", expression(&children[0], 0), params.join(", "), returns));
+  block(out, &children[1..], 1);
+  out.push_str("}\n");
+}
+
+//the kind of a top-level item, items of different kinds and functions are separated by a blank line
+fn kind(node: &Node) -> &'static str {
+  match node {
+    Node::Import{..} | Node::Use{..} => "import",
+    Node::Statement{..} => "global",
+    Node::FunctionDefine{..} => "fn",
+    _ => "comment",
+  }
+}
+
+// Turns a parsed program back into source with the canonical layout.
+//Each statement is on its own line, indented by two spaces per block, with one space around operators
+//and only the parentheses the precedence needs. Comments between statements and items are kept,
+//comments inside a statement are not in the tree and are dropped.
+pub fn format_program(program: &Node) -> String {
+  let children = program.children();
+  let mut out = String::new();
+  let mut previous: Option<&str> = None;
+  for (index, child) in children.iter().enumerate() {
+    if let Node::Comment{value, trailing: true} = child {
+      if previous.is_some() {
+        trailing(&mut out, value);
+        continue;
+      }
+    }
+    //comments above an item are separated from what comes before like the item is
+    let current = match child {
+      Node::Comment{..} => children[index..].iter()
+        .map(kind)
+        .find(|kind| *kind != "comment")
+        .unwrap_or("comment"),
+      _ => kind(child),
+    };
+    if let Some(previous) = previous {
+      if previous != "comment" && (previous != current || current == "fn") {
+        out.push('\n');
+      }
+    }
+    previous = Some(kind(child));
+
+    out.push_str(&child.to_source());
+    out.push('\n');
+  }
+  out
+}
+
+impl Node {
+  // Renders the node as source, with only the parentheses the l1/l2/l3 precedence levels need.
+  //Works on any subtree, e.g. a rewritten MathExpression, a statement or a whole program.
+  //Nodes that span several lines (if expressions, functions) are indented as if they start a line.
+  pub fn to_source(&self) -> String {
+    match self {
+      Node::Program{..} => format_program(self),
+      Node::FunctionDefine{children, ..} => {
+        let mut out = String::new();
+        function(&mut out, children);
+        out.pop();
+        out
+      },
+      Node::Statement{children, ..} => statement(&children[0], 0),
+      Node::VariableDefine{..} | Node::ConstantDefine{..} | Node::FunctionReturn{..} | Node::ExpressionStatement{..} => statement(self, 0),
+      Node::IfStatements{..} => braces(self, 0),
+      Node::ElseIfExpression{children} => format!("else if {} {}", operand(&children[0], 2, 0), braces(&children[1], 0)),
+      Node::ElseExpression{children} => format!("else {}", braces(&children[0], 0)),
+      Node::FunctionArguments{children} => {
+        let items: Vec<String> = children.iter().map(|child| child.to_source()).collect();
+        items.join(", ")
+      },
+      Node::Parameter{children} => format!("{}{}", expression(&children[0], 0), annotation(&children[1..])),
+      Node::Type{name} => name.to_string(),
+      Node::Comment{value, ..} => value.to_string(),
+      Node::Import{path} => format!("import \"{}\";", path),
+      Node::Use{path} => format!("use {};", path),
+      _ => expression(self, 0),
+    }
+  }
+}
+
+//the comments of source with their byte offsets, in order, text in strings is skipped
+fn comments(source: &str) -> Vec<(usize, &str)> {
+  let mut found = Vec::new();
+  let mut rest = source;
+  while let Some(c) = rest.chars().next() {
+    if let Ok((after, value)) = comment(rest) {
+      found.push((source.len() - rest.len(), value));
+      rest = after;
+    } else if let Ok((after, _)) = string(rest) {
+      rest = after;
+    } else {
+      rest = &rest[c.len_utf8()..];
+    }
+  }
+  found
+}
+
+// Parses and formats source, fails if it is not a whole valid program.
+//Also fails instead of dropping a comment the layout has no place for, e.g. one inside an expression,
+//between ) and { or between } and else.
+pub fn format_source(source: &str) -> Result<String, String> {
+  let formatted = match program(source) {
+    Ok((unparsed, tree)) => {
+      if !unparsed.trim().is_empty() {
+        return Err(format!("unparsed text {:?}", unparsed));
+      }
+      format_program(&tree)
+    },
+    Err(error) => return Err(format!("{:?}", error)),
+  };
+  let kept: Vec<&str> = comments(&formatted).into_iter().map(|(_, value)| value).collect();
+  for (index, (offset, value)) in comments(source).into_iter().enumerate() {
+    if kept.get(index) != Some(&value) {
+      let line = source[..offset].matches('\n').count() + 1;
+      return Err(format!("the comment {:?} on line {} would be dropped", value, line));
+    }
+  }
+  Ok(formatted)
+}
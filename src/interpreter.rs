@@ -1,33 +1,507 @@
-use crate::parser::Node;
-use std::collections::HashMap;
+use crate::module::{ModuleError, ModuleLoader};
+use crate::parser::{Node, Span};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 
 const RETURN_VAR: i32 = 0;
 
-#[derive(Debug, PartialEq, Clone)]
+// How many calls may be active at once unless the host sets another limit with set_max_depth.
+pub const DEFAULT_MAX_DEPTH: usize = 400;
+
+// How many bytes of the host's stack the calls of the tree walker may use unless the host sets another limit with set_max_stack.
+//A call takes anything from one to tens of kilobytes depending on the build and how deeply its ifs and expressions nest,
+//so the depth alone can not keep it from overflowing. This is half of what a thread spawned by std gets.
+pub const DEFAULT_MAX_STACK: usize = 1 << 20;
+
+//the error a call fails with when it would go deeper than the limit, the calls are in Runtime::stack_trace
+pub(crate) const STACK_OVERFLOW: &str = "Stack overflow";
+//the errors a run stops with when the host's limits are reached, see set_step_budget, set_timeout and interrupt_handle
+pub(crate) const BUDGET_EXCEEDED: &str = "Step budget exceeded";
+pub(crate) const TIMED_OUT: &str = "Timed out";
+pub(crate) const INTERRUPTED: &str = "Interrupted";
+pub(crate) const OUT_OF_MEMORY: &str = "Memory limit exceeded";
+
+//how many steps are run between looks at the clock and the interrupt flag
+const CHECK_EVERY: u64 = 256;
+
+// Errors that end the run where they happen.
+//Other errors in an operand become the error of the operator, these are passed on as they are.
+pub(crate) fn is_fatal(error: &str) -> bool {
+  [STACK_OVERFLOW, BUDGET_EXCEEDED, TIMED_OUT, INTERRUPTED, OUT_OF_MEMORY].contains(&error)
+}
+
+// Stops a running script from another thread, see Runtime::interrupt_handle.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+  flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+  // Makes the script fail with "Interrupted" within a few steps.
+  //If it is not running, the next run of the runtime fails instead.
+  pub fn interrupt(&self) {
+    self.flag.store(true, Ordering::SeqCst);
+  }
+}
+
+impl Value {
+  // The bytes the value keeps on the heap, what counts towards the runtime's memory limit.
+  pub fn size(&self) -> usize {
+    match self {
+      Value::String(value) => value.len(),
+      _ => 0,
+    }
+  }
+
+  // Checks the value against a type annotation (int, bool, string or null).
+  pub fn has_type(&self, name: &str) -> bool {
+    matches!((self, name), (Value::Number(_), "int") | (Value::Bool(_), "bool") | (Value::String(_), "string") | (Value::Null, "null"))
+  }
+}
+
+// How values are shown by print, println and format.
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::String(value) => write!(f, "{}", value),
+      Value::Number(value) => write!(f, "{}", value),
+      Value::Bool(value) => write!(f, "{}", value),
+      Value::Null => write!(f, "null"),
+    }
+  }
+}
+
+//where print and println write to
+pub enum Output {
+  Stdout,
+  //kept in memory until it is read with Runtime::take_output
+  Buffer(Vec<u8>),
+  //any other writer, e.g. a file or a socket
+  Writer(Box<dyn Write>),
+}
+
+impl fmt::Debug for Output {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Output::Stdout => write!(f, "Stdout"),
+      Output::Buffer(buffer) => write!(f, "Buffer({:?})", String::from_utf8_lossy(buffer)),
+      Output::Writer(_) => write!(f, "Writer"),
+    }
+  }
+}
+
+// How Runtime runs main once the program is loaded.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Engine {
+  //walks the parse tree
+  Tree,
+  //compiles the functions to bytecode and runs them on a stack VM, see vm.rs
+  Bytecode,
+}
+
+// Told about each statement of the root program before the tree walker runs it, see Runtime::set_debugger.
+//It can look at the runtime, e.g. the backtrace and the current frame, and pause by not returning until the
+//user wants to go on. An error stops the script with that error.
+pub trait Debugger {
+  fn before(&mut self, runtime: &mut Runtime, span: Span) -> Result<(), &'static str>;
+}
+
+impl fmt::Debug for dyn Debugger {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Debugger")
+  }
+}
+
+//what an expression in return position evaluates to, a call there is made by call_function after leaving the frame
+enum Tail {
+  Value(Value),
+  Call(String, Vec<Value>),
+}
+
+//a function implemented in Rust that scripts can call, it gets the runtime and the evaluated arguments
+pub type HostFunction = fn(&mut Runtime, &[Value]) -> Result<Value, &'static str>;
+
+//an if block being run: the index of the frame it is in and the bindings it replaced there,
+//with the value they had before (None if the name was new)
+type Block = (usize, Vec<(String, Option<Value>)>);
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Value {
   String(String),
   Number(i32),
   Bool(bool),
+  //the value of an if without a matching branch, or of a block that does not return
+  Null,
 }
 
 #[derive(Debug)]
 pub struct Runtime {
   //holds function name, and function statements
-  functions: HashMap<String, Vec<Node>>,
+  //the statements are shared, so a call borrows them instead of copying the body
+  pub(crate) functions: HashMap<String, Rc<Vec<Node>>>,
 
   //holds stack of stack frames
   //stack is used when entering a function and holding variables from statements (e.g let x = 4)
   stack: Vec<HashMap<String, Value>>,
+  //the if blocks being run, innermost last. A block shares the frame of its function,
+  //its lets are undone when it ends instead of being made in a copy of the frame
+  blocks: Vec<Block>,
+
+  //holds top-level let/const bindings, visible from every function
+  pub(crate) globals: HashMap<String, Value>,
+  //names of globals declared with const, these can not be reassigned
+  pub(crate) constants: HashSet<String>,
+
+  //stack of the module namespaces being run, the root program is ""
+  modules: Vec<String>,
+  //functions brought into a module with use, e.g. "helper" -> "util::helper"
+  pub(crate) aliases: HashMap<String, String>,
+  //parses and caches imported files
+  loader: ModuleLoader,
+  //files whose functions and globals have already been registered
+  loaded: HashSet<PathBuf>,
+  //the file each module namespace was taken by, two files with the same name can not both be imported
+  namespaces: HashMap<String, PathBuf>,
+
+  //functions provided by the host, called when the program does not define a function with the same name
+  pub(crate) host_functions: HashMap<String, HostFunction>,
+
+  //where print and println write to, stdout unless the host sets something else
+  output: Output,
+
+  engine: Engine,
+
+  //the calls being run, outermost first, and how many there may be
+  pub(crate) calls: Vec<String>,
+  pub(crate) max_depth: usize,
+  //where the host's stack was when the outermost call started, and how far past it the calls may go
+  stack_base: usize,
+  max_stack: usize,
+  //the calls that were active when the last stack overflow happened
+  pub(crate) trace: Vec<String>,
+
+  //steps the script may still take, a step is a node in the tree walker or an instruction in the VM
+  budget: Option<u64>,
+  //when the script has to stop
+  deadline: Option<Instant>,
+  interrupted: Arc<AtomicBool>,
+  //steps taken since the clock and the interrupt flag were last looked at
+  unchecked: u64,
+
+  //bytes held by the values in frames, globals and VM locals, and how many there may be
+  memory: usize,
+  memory_limit: Option<usize>,
+
+  //called before each statement, it is taken out while it runs so it can be handed the runtime
+  debugger: Option<Box<dyn Debugger>>,
 }
 
 impl Runtime {
 
   pub fn new() -> Runtime {
-    Runtime {
+    let mut runtime = Runtime {
       functions: HashMap::new(),
       
       stack: Vec::new(),
-      
+      blocks: Vec::new(),
+
+      globals: HashMap::new(),
+      constants: HashSet::new(),
+
+      modules: Vec::new(),
+      aliases: HashMap::new(),
+      loader: ModuleLoader::new(),
+      loaded: HashSet::new(),
+      namespaces: HashMap::new(),
+
+      host_functions: HashMap::new(),
+
+      output: Output::Stdout,
+
+      engine: Engine::Tree,
+
+      calls: Vec::new(),
+      max_depth: DEFAULT_MAX_DEPTH,
+      stack_base: 0,
+      max_stack: DEFAULT_MAX_STACK,
+      trace: Vec::new(),
+
+      budget: None,
+      deadline: None,
+      interrupted: Arc::new(AtomicBool::new(false)),
+      unchecked: 0,
+
+      memory: 0,
+      memory_limit: None,
+
+      debugger: None,
+    };
+    runtime.register("is_null", is_null);
+    runtime.register("assert", assert);
+    runtime.register("print", print);
+    runtime.register("println", println);
+    runtime.register("format", format);
+    runtime
+  }
+
+  // Sends everything the script prints to output from now on.
+  pub fn set_output(&mut self, output: Output) {
+    self.output = output;
+  }
+
+  // Returns what has been printed into an Output::Buffer and empties the buffer.
+  //Other outputs are not kept, so this returns an empty string for them.
+  pub fn take_output(&mut self) -> String {
+    match &mut self.output {
+      Output::Buffer(buffer) => String::from_utf8_lossy(&std::mem::take(buffer)).to_string(),
+      _ => String::new(),
+    }
+  }
+
+  // Writes text to the output.
+  pub fn write(&mut self, text: &str) -> Result<(), &'static str> {
+    let result = match &mut self.output {
+      Output::Stdout => std::io::stdout().write_all(text.as_bytes()),
+      Output::Buffer(buffer) => buffer.write_all(text.as_bytes()),
+      Output::Writer(writer) => writer.write_all(text.as_bytes()),
+    };
+    result.map_err(|_| "Could not write output")
+  }
+
+  // Selects how main is run, the results are the same with either engine.
+  pub fn set_engine(&mut self, engine: Engine) {
+    self.engine = engine;
+  }
+
+  // Limits how many calls may be active at once, a call beyond it fails with "Stack overflow".
+  //Tail calls replace the call that makes them, so they do not count towards the limit.
+  pub fn set_max_depth(&mut self, depth: usize) {
+    self.max_depth = depth;
+  }
+
+  // Limits how many bytes of the host's stack the calls of the tree walker may use, a call beyond it fails with "Stack overflow".
+  //Hosts that run scripts on threads with smaller stacks than the 2MB std gives them should lower it.
+  pub fn set_max_stack(&mut self, bytes: usize) {
+    self.max_stack = bytes;
+  }
+
+  // The calls that were active when the last stack overflow happened, outermost first, ending with the call that failed.
+  pub fn stack_trace(&self) -> &[String] {
+    &self.trace
+  }
+
+  // Turns an error of the script into a ModuleError, with the call trace if it is a stack overflow.
+  pub fn runtime_error(&self, message: &'static str) -> ModuleError {
+    if message == STACK_OVERFLOW {
+      ModuleError::StackOverflow(self.trace.clone())
+    } else {
+      ModuleError::Runtime(message)
+    }
+  }
+
+  // Lets the script take at most steps more steps, after that it fails with "Step budget exceeded".
+  //The budget is used by loading programs too, global initializers run code. None removes the limit.
+  pub fn set_step_budget(&mut self, steps: Option<u64>) {
+    self.budget = steps;
+  }
+
+  // The steps the script may still take, None if there is no budget.
+  pub fn remaining_steps(&self) -> Option<u64> {
+    self.budget
+  }
+
+  // Makes the script fail with "Timed out" once timeout has passed from now. None removes the limit.
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+  }
+
+  // A handle that can stop the script from another thread, e.g. when the user cancels a request.
+  pub fn interrupt_handle(&self) -> InterruptHandle {
+    InterruptHandle{flag: Arc::clone(&self.interrupted)}
+  }
+
+  //counts a step of the script, and stops it when one of the limits set by the host is reached
+  pub(crate) fn step(&mut self) -> Result<(), &'static str> {
+    if let Some(budget) = self.budget {
+      if budget == 0 {
+        return Err(BUDGET_EXCEEDED);
+      }
+      self.budget = Some(budget - 1);
+    }
+    //reading the clock takes longer than most steps, so it is only done every so often
+    self.unchecked += 1;
+    if self.unchecked < CHECK_EVERY {
+      return Ok(());
+    }
+    self.unchecked = 0;
+    if self.interrupted.swap(false, Ordering::SeqCst) {
+      return Err(INTERRUPTED);
+    }
+    match self.deadline {
+      Some(deadline) if Instant::now() >= deadline => Err(TIMED_OUT),
+      _ => Ok(()),
+    }
+  }
+
+  // Limits the bytes the values of the script may hold, see Value::size. None removes the limit.
+  //Storing a value that goes over it, or a host function returning one, fails with "Memory limit exceeded".
+  pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+    self.memory_limit = bytes;
+  }
+
+  // The bytes held by the values in the frames of running functions and in the globals.
+  pub fn memory_used(&self) -> usize {
+    self.memory
+  }
+
+  //fails if size more bytes would go over the memory limit
+  pub(crate) fn fits(&self, size: usize) -> Result<(), &'static str> {
+    match self.memory_limit {
+      Some(limit) if self.memory + size > limit => Err(OUT_OF_MEMORY),
+      _ => Ok(()),
+    }
+  }
+
+  //accounts for a value the script holds on to
+  pub(crate) fn hold(&mut self, size: usize) -> Result<(), &'static str> {
+    self.fits(size)?;
+    self.memory += size;
+    Ok(())
+  }
+
+  pub(crate) fn release(&mut self, size: usize) {
+    self.memory = self.memory.saturating_sub(size);
+  }
+
+  fn pop_frame(&mut self) {
+    if let Some(frame) = self.stack.pop() {
+      self.release(frame.values().map(Value::size).sum());
+    }
+  }
+
+  //stores a variable in the current frame, replacing any variable with the same name
+  //a let or a parameter can not take the name of a global const
+  fn bind(&mut self, name: &str, value: Value) -> Result<(), &'static str> {
+    if self.constants.contains(&self.qualify(name)) {
+      return Err("Cannot reassign constant");
+    }
+    self.hold(value.size())?;
+    let old = match self.stack.last_mut() {
+      Some(frame) => frame.insert(name.to_string(), value),
+      None => return Err("no scope has been init"),
+    };
+    //a block keeps the value it replaced until it ends
+    let frame = self.stack.len() - 1;
+    match self.blocks.last_mut() {
+      Some((block_frame, replaced)) if *block_frame == frame => replaced.push((name.to_string(), old)),
+      _ => if let Some(old) = old {
+        self.release(old.size());
+      },
+    }
+    Ok(())
+  }
+
+  //starts an if block in the current frame
+  fn enter_block(&mut self) {
+    self.blocks.push((self.stack.len() - 1, Vec::new()));
+  }
+
+  //ends the innermost if block, the bindings it made are taken out and the ones it replaced are put back
+  fn leave_block(&mut self) {
+    let (frame, replaced) = match self.blocks.pop() {
+      Some(block) => block,
+      None => return,
+    };
+    for (name, old) in replaced.into_iter().rev() {
+      let current = match old {
+        Some(old) => self.stack[frame].insert(name, old),
+        None => self.stack[frame].remove(&name),
+      };
+      if let Some(current) = current {
+        self.release(current.size());
+      }
+    }
+  }
+
+  // Calls debugger before each statement of the root program. Only the tree walker has statements to stop at,
+  //the bytecode engine runs without it.
+  pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) {
+    self.debugger = debugger;
+  }
+
+  // The calls being run, outermost first, so the last one is the function of the current statement.
+  pub fn backtrace(&self) -> &[String] {
+    &self.calls
+  }
+
+  // The variables of the innermost frame, the parameters and lets of the current call and its blocks.
+  pub fn frame(&self) -> Option<&HashMap<String, Value>> {
+    self.stack.last()
+  }
+
+  //hands the statement at span to the debugger, statements of imported modules are not shown
+  //as the spans are offsets into files the debugger does not know
+  fn debug(&mut self, span: Span) -> Result<(), &'static str> {
+    if self.modules.last().map(|module| !module.is_empty()).unwrap_or(false) {
+      return Ok(());
+    }
+    match self.debugger.take() {
+      Some(mut debugger) => {
+        let result = debugger.before(self, span);
+        self.debugger = Some(debugger);
+        result
+      },
+      None => Ok(()),
+    }
+  }
+
+  //starts a call, unless there are already as many calls as max_depth allows or they use more than max_stack
+  pub(crate) fn enter(&mut self, name: &str) -> Result<(), &'static str> {
+    //the address of a local tells how much of the host's stack is in use
+    let here = 0u8;
+    let address = &here as *const u8 as usize;
+    if self.calls.is_empty() {
+      self.stack_base = address;
+    }
+    if self.calls.len() >= self.max_depth || self.stack_base.abs_diff(address) > self.max_stack {
+      self.trace = self.calls.clone();
+      self.trace.push(name.to_string());
+      return Err(STACK_OVERFLOW);
+    }
+    self.calls.push(name.to_string());
+    Ok(())
+  }
+
+  // Makes a host function callable from scripts under name, replacing any host function with the same name.
+  pub fn register(&mut self, name: &str, function: HostFunction) {
+    self.host_functions.insert(name.to_string(), function);
+  }
+
+  // Loads the program and runs its main function.
+  pub fn start(&mut self, node: &Node) -> Result<Value, &'static str> {
+    //when we run this the first time, we are collecting all the function definitions into functions data strcutre
+    //and evaluating the global let/const bindings
+    //there is no importing file, so imports are resolved relative to the current directory
+    self.load_module(node, "", Path::new(".")).map_err(|e| e.message())?;
+    self.run_main()
+  }
+
+  // Runs the main function of the loaded program with the selected engine.
+  pub fn run_main(&mut self) -> Result<Value, &'static str> {
+    match self.engine {
+      Engine::Tree => {
+        //this will begin the main program, by going to check if main is a function, and then going from there (goes into main function and checks for other func calls)
+        let start_main = Node::FunctionCall{name: "main".to_string(), children: vec![], span: Span::default()};
+        self.run(&start_main)
+      },
+      Engine::Bytecode => crate::vm::run_main(self),
     }
   }
 
@@ -43,10 +517,12 @@ impl Runtime {
                   match &children[0] {
                     Node::MathExpression{name,children} => v.push(Node::MathExpression{name: name.to_string(),children: children.to_vec()}),
                     Node::Number{value} => v.push(Node::Number{value: *value}),
-                    Node::Identifier{value} => v.push(Node::Identifier{value: value.to_string()}),
+                    Node::Identifier{value, span} => v.push(Node::Identifier{value: value.to_string(), span: *span}),
                     _ => ()
                   }
                 }
+                //parameters of a function definition keep their type annotation
+                Node::Parameter{..} => v.push(child.clone()),
                 _ => ()
               }
           }
@@ -54,10 +530,12 @@ impl Runtime {
       Node::IfStatements{children}=>{
         for child in children {
           match child{
-            Node::Statement{children} => {
+            Node::Statement{children, ..} => {
               match &children[0] {
                 Node::VariableDefine{children} => v.push(Node::VariableDefine{children: children.to_vec()}),
                 Node::FunctionReturn{children} => v.push(Node::FunctionReturn{children: children.to_vec()}),
+                Node::IfExpression{children} => v.push(Node::IfExpression{children: children.to_vec()}),
+                Node::ExpressionStatement{children} => v.push(Node::ExpressionStatement{children: children.to_vec()}),
                 _=>()
               }
             },
@@ -72,81 +550,378 @@ impl Runtime {
 
   }
 
-  pub fn run(&mut self, node: &Node) -> Result<Value, &'static str> {
-    match node {
-      Node::Program{children} => {
-        // the children in program only consist of funcdefinitons, or things that make up functions (look at grammar)
-        for child in children {
-          
-          match child {
-            
-            Node::FunctionDefine{..} => {
-              self.run(child);
-            },
-            //this is only if program is just an expression
-            Node::Expression{..} => {
-              self.functions.insert("main".to_string(), vec![Node::FunctionReturn{children: vec![child.clone()]}]);
-            },
-            //this is if program holds just statements
-            Node::Statement{..} => {
-              self.functions.insert("main".to_string(), vec![child.clone()]);
-            },
+  // Prefixes a function or global name with the module currently being run.
+  //Names in the root program are left as they are.
+  fn qualify(&self, name: &str) -> String {
+    match self.modules.last() {
+      Some(module) if !module.is_empty() => format!("{}::{}", module, name),
+      _ => name.to_string(),
+    }
+  }
+
+  // Finds the key in functions that a call refers to.
+  //Functions of the current module come first, then names brought in with use, then the name as written (root or module::name).
+  fn resolve_function(&self, name: &str) -> String {
+    let local = self.qualify(name);
+    if self.functions.contains_key(&local) {
+      return local;
+    }
+    match self.aliases.get(&local) {
+      Some(target) => target.to_string(),
+      None => name.to_string(),
+    }
+  }
 
-            x => {return Err("Unimplemeneted 1");},
+  // Registers a parsed program as the module namespace, loading its imports first.
+  //Import paths are resolved relative to dir, the directory of the importing file.
+  pub fn load_module(&mut self, program: &Node, namespace: &str, dir: &Path) -> Result<(), ModuleError> {
+    if let Node::Program{children} = program {
+      for child in children {
+        if let Node::Import{path} = child {
+          let file = ModuleLoader::resolve(dir, path)?;
+          if self.loaded.contains(&file) {
+            continue;
           }
+          let module_namespace = ModuleLoader::namespace(&file);
+          match self.namespaces.get(&module_namespace) {
+            Some(other) if *other != file => return Err(ModuleError::Clash(module_namespace, other.clone(), file)),
+            _ => { self.namespaces.insert(module_namespace.clone(), file.clone()); },
+          }
+          let module = self.loader.enter(&file)?;
+          let module_dir = file.parent().unwrap_or(dir).to_path_buf();
+          let result = self.load_module(&module, &module_namespace, &module_dir);
+          self.loader.leave();
+          result?;
+          self.loaded.insert(file);
         }
-        Ok(Value::Bool(true))
+      }
+    }
+
+    self.modules.push(namespace.to_string());
+    let result = self.run(program);
+    self.modules.pop();
+    result.map(|_| ()).map_err(|error| self.runtime_error(error))
+  }
+
+  // Loads the program in path, and everything it imports, as the root module.
+  pub fn load_file(&mut self, path: &Path) -> Result<(), ModuleError> {
+    let file = ModuleLoader::resolve(Path::new("."), &path.to_string_lossy())?;
+    let program = self.loader.enter(&file)?;
+    let dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let result = self.load_module(&program, "", &dir);
+    self.loader.leave();
+    self.loaded.insert(file);
+    result
+  }
+
+  //evaluates an operand of math or a comparison, its error is kept for the operator unless it is fatal
+  fn operand(&mut self, node: &Node) -> Result<Result<Value, &'static str>, &'static str> {
+    match self.run(node) {
+      Err(error) if is_fatal(error) => Err(error),
+      result => Ok(result),
+    }
+  }
+
+  //evaluates the arguments of a call in the current frame
+  fn arguments(&mut self, children: &[Node]) -> Result<Vec<Value>, &'static str> {
+    let mut val = Vec::new();
+    //This extracts funcargs node from children in func call
+    for args in children{
+      if let Node::FunctionArguments{children} = args {
+        for arg in children {
+          val.push(self.run(arg)?);
+        }
+      }
+    }
+    Ok(val)
+  }
+
+  // Picks the block an if expression runs, the first one whose condition is true or the else block.
+  //None when no branch is taken and there is no else.
+  fn branch<'a>(&mut self, children: &'a [Node]) -> Result<Option<&'a Node>, &'static str> {
+    //evals to a bool
+    match self.run(&children[0])? {
+      Value::Bool(true) => return Ok(Some(&children[1])),
+      Value::Bool(false) => (),
+      _ => return Err("no match for if expression"),
+    };
+
+    //enters the first elseif node whose condition is true, or the else node
+    for child in &children[2..] {
+      match child {
+        Node::ElseIfExpression{children} => match self.run(&children[0])? {
+          Value::Bool(true) => return Ok(Some(&children[1])),
+          Value::Bool(false) => (),
+          _ => return Err("no match for if expression"),
+        },
+        Node::ElseExpression{children} => return Ok(Some(&children[0])),
+        _ => (),
+      }
+    }
+    Ok(None)
+  }
+
+  // Runs the statements of an if block in a scope that contains the variables of the current one.
+  //When the if is in return position of a function, tail is true and the block's return is evaluated with tail().
+  //Returns None if the block ended without a return.
+  fn block(&mut self, children: &[Node], tail: bool) -> Result<Option<Tail>, &'static str> {
+    if self.stack.is_empty() {
+      return Err("no scope has been init");
+    }
+    self.enter_block();
+
+    let mut result = Ok(None);
+    //the statements are run where they are in the tree, other kinds of statements are skipped like in extract_val
+    let statements = children.iter().filter(|child| match child {
+      Node::Statement{children, ..} => matches!(&children[0],
+        Node::VariableDefine{..} | Node::FunctionReturn{..} | Node::IfExpression{..} | Node::ExpressionStatement{..}),
+      _ => false,
+    });
+    for statement in statements {
+      match statement {
+        //return gives the block its value, the statements after it are not run
+        Node::Statement{children, span} if matches!(&children[0], Node::FunctionReturn{..}) => {
+          result = self.debug(*span).and_then(|_| self.returned(&children[0].children()[0], tail)).map(Some);
+          break;
+        },
+        //a return in an if statement is the return of this block
+        Node::Statement{children, span} if matches!(&children[0], Node::IfExpression{..}) => {
+          result = self.debug(*span).and_then(|_| self.if_statement(children[0].children(), tail));
+          if let Ok(None) = result {
+            continue;
+          }
+          break;
+        },
+        _ => {
+          if let Err(error) = self.run(statement) {
+            result = Err(error);
+            break;
+          }
+        },
+      }
+    }
+
+    //leave the scope of the block
+    self.leave_block();
+    result
+  }
+
+  // Evaluates the expression a function returns.
+  //A call to a function of the script is not made here, its name and arguments are handed back so that
+  //call_function can make it once the frame of the returning function is gone. Ifs pass this on to their blocks.
+  fn tail(&mut self, node: &Node) -> Result<Tail, &'static str> {
+    match node {
+      Node::Expression{children} if !children.is_empty() => self.tail(&children[0]),
+      Node::FunctionCall{name, children, ..} => {
+        let qualified_name = self.resolve_function(name);
+        if !self.functions.contains_key(&qualified_name) {
+          return self.run(node).map(Tail::Value);
+        }
+        let val = self.arguments(children)?;
+        Ok(Tail::Call(qualified_name, val))
       },
-    
+      Node::IfExpression{children} => match self.branch(children)? {
+        Some(block) => self.block(block.children(), true).map(|result| result.unwrap_or(Tail::Value(Value::Null))),
+        None => Ok(Tail::Value(Value::Null)),
+      },
+      _ => self.run(node).map(Tail::Value),
+    }
+  }
 
-      Node::IfExpression{children} => {
-        //evals to a bool
-        let if_conditional = self.run(&children[0]);
-
-        let mut result = Ok(Value::Bool(false));
-
-         result = match if_conditional {
-
-          //this returns the value of the first ifstatements node
-          Ok(Value::Bool(true)) => self.run(&children[1]),
-          //enters a elseif node or else node
-          Ok(Value::Bool(false)) => {
-            let mut index = 2;
-            let target_value = Ok(Value::Bool(false));
-            while index < children.len() && result == target_value {
-              // Your loop code here
-              result = self.run(&children[index]);
-              index += 1;
-            };
-            
+  // Runs an if that is a statement, its value is not used.
+  //A return in the block that is taken returns from the enclosing function or block, Some holds its value.
+  fn if_statement(&mut self, children: &[Node], tail: bool) -> Result<Option<Tail>, &'static str> {
+    match self.branch(children)? {
+      Some(block) => self.block(block.children(), tail),
+      None => Ok(None),
+    }
+  }
 
-            if result == target_value {
-              self.run(&children[index])
+  // Runs a function of the script with evaluated arguments.
+  //Calls in return position are tail calls: the frame of the function is left before the call is made, so
+  //tail-recursive scripts run in constant stack space. Functions with a return annotation still make the call
+  //inside their frame, the value has to be checked against the annotation when the call returns.
+  fn call_function(&mut self, mut qualified_name: String, mut val: Vec<Value>) -> Result<Value, &'static str> {
+    self.enter(&qualified_name)?;
+    loop {
+      let func_statements = match self.functions.get(&qualified_name) {
+        Some(val) => Rc::clone(val),
+        None => {
+          self.calls.pop();
+          return Err("Undefined function");
+        },
+      };
+      let return_type = func_statements.iter().find(|statement| matches!(statement, Node::Type{..}));
+
+      //a function without a return evaluates to null
+      let mut result = Ok(Tail::Value(Value::Null));
+      //the body runs inside the module the function was defined in, so its own calls and globals resolve there
+      let namespace = match qualified_name.rfind("::") {
+        Some(index) => qualified_name[..index].to_string(),
+        None => String::new(),
+      };
+      self.modules.push(namespace);
+      self.stack.push(HashMap::new());
+
+      //the parameters come first in func_statements, then the return type if there is one, then the statements
+      let mut counter = 0;
+      for statement in func_statements.iter(){
+        match statement{
+
+          Node::Parameter{children} => {
+            let arg = match val.get(counter) {
+              Some(arg) => arg.to_owned(),
+              None => {result = Err("Missing argument"); break;}
+            };
+            if let Err(error) = Self::check_annotation(Ok(arg.clone()), children.get(1), "Argument does not match its type annotation") {
+              result = Err(error);
+              break;
             }
-            else{
-              result
+            if let Node::Identifier{value, ..} = &children[0] {
+              if let Err(error) = self.bind(value, arg) {
+                result = Err(error);
+                break;
+              }
             }
+            counter +=1;
+          },
+          Node::Type{..} => (),
 
+          Node::Identifier{value, ..} => {
+            let arg = match val.get(counter) {
+              Some(arg) => arg.to_owned(),
+              None => {result = Err("Missing argument"); break;}
+            };
+            if let Err(error) = self.bind(value, arg) {
+              result = Err(error);
+              break;
+            }
+            counter +=1;
+          },
+          //return ends the function, the statements after it are not run
+          Node::Statement{children, span} => match &children[0] {
+            Node::FunctionReturn{children} => {
+              result = self.debug(*span).and_then(|_| self.returned(&children[0], return_type.is_none()));
+              break;
+            },
+            Node::IfExpression{children} => match self.debug(*span).and_then(|_| self.if_statement(children, return_type.is_none())) {
+              Ok(None) => (),
+              Ok(Some(tail)) => {
+                result = Ok(tail);
+                break;
+              },
+              Err(error) => {
+                result = Err(error);
+                break;
+              },
+            },
+            _ => {
+              if let Err(error) = self.run(statement) {
+                result = Err(error);
+                break;
+              }
+            },
           },
-          _ => Err("no match for if expression")
+          Node::FunctionReturn{children} => {
+            result = self.returned(&children[0], return_type.is_none());
+            break;
+          },
+          _=> {result = Err("node is not a variableDefine or functionReturn"); break;}
         };
+      };
+
+      //leave the scope of the function
+      self.pop_frame();
+      self.modules.pop();
+      match result {
+        Ok(Tail::Call(name, args)) => {
+          if let Some(call) = self.calls.last_mut() {
+            *call = name.to_string();
+          }
+          qualified_name = name;
+          val = args;
+        },
+        Ok(Tail::Value(value)) => {
+          self.calls.pop();
+          return Self::check_annotation(Ok(value), return_type, "Return value does not match its type annotation");
+        },
+        Err(error) => {
+          self.calls.pop();
+          return Err(error);
+        },
+      }
+    }
+  }
 
-        
+  //the value of a return statement, with a pending tail call when tail is true
+  fn returned(&mut self, node: &Node, tail: bool) -> Result<Tail, &'static str> {
+    if tail {
+      self.tail(node)
+    } else {
+      self.run(node).map(Tail::Value)
+    }
+  }
 
-         result
+  // Fails with message if the value does not have the type of the annotation.
+  //Passes the result through when there is no annotation or it already is an error.
+  fn check_annotation(result: Result<Value, &'static str>, annotation: Option<&Node>, message: &'static str) -> Result<Value, &'static str> {
+    match (result, annotation) {
+      (Ok(value), Some(Node::Type{name})) if !value.has_type(name) => Err(message),
+      (result, _) => result,
+    }
+  }
 
-      },
-      
-      Node::ElseIfExpression{children} =>{
-        let conditional = self.run(&children[0]);
+  // Evaluates a top-level let or const and stores it in the globals.
+  //A global initializer runs in its own empty frame, so it can only see functions and earlier globals.
+  fn define_global(&mut self, node: &Node) -> Result<(), &'static str> {
+    let (children, is_const) = match node {
+      Node::VariableDefine{children} => (children, false),
+      Node::ConstantDefine{children} => (children, true),
+      _ => return Err("node is not a global let or const"),
+    };
 
-        if conditional == Ok(Value::Bool(false)){
-          return conditional
-        }
+    let var_name = match &children[0] {
+      Node::Identifier{value, ..} => value.to_string(),
+      _ => return Err("Not valid ID (global define)"),
+    };
 
-        self.run(&children[1])
+    let var_name = self.qualify(&var_name);
+    if self.constants.contains(&var_name) {
+      return Err("Cannot reassign constant");
+    }
 
+    self.stack.push(HashMap::new());
+    let result = self.run(&children[1]);
+    self.stack.pop();
+    let result = Self::check_annotation(result, children.get(2), "Value does not match its type annotation");
 
+    if is_const {
+      self.constants.insert(var_name.clone());
+    }
+    let result = result?;
+    self.hold(result.size())?;
+    if let Some(old) = self.globals.insert(var_name, result) {
+      self.release(old.size());
+    }
+    Ok(())
+  }
+
+  //run nests once for every node of an expression, the arms that need many locals are methods of their own
+  //so its frame stays small and a debug build fits more calls into max_stack
+  pub fn run(&mut self, node: &Node) -> Result<Value, &'static str> {
+    self.step()?;
+    match node {
+      Node::Program{children} => self.program(children),
+    
+
+      //children are the condition, the ifstatements node, any elseif nodes and an optional else node
+      Node::IfExpression{children} => {
+        match self.branch(children)? {
+          //this returns the value of the ifstatements node that was picked
+          Some(block) => self.run(block),
+          //no branch was taken and there is no else
+          None => Ok(Value::Null),
+        }
       },
       
 
@@ -160,29 +935,11 @@ impl Runtime {
 
 
       //traverse thru children and return the final result
-      Node::IfStatements{children} => {
-
-        //create new scope that contains the variables of the previous stack
-        let clone_map = self.stack.last().expect("no stack has been initialized").clone();
-        self.stack.push(clone_map);
-        
-
-        let extract_statements = Self::extract_val(&Node::IfStatements{children:children.to_vec()});
-        //child is a statement
-        match extract_statements.last().expect("no statements in if expression") {
-          Node::FunctionReturn{children} => (),
-          _ => {println!("Error: Final statement for for loop is not a return"); std::process::exit(1); }
-
-        };
-        
-        let mut result = Ok(Value::Bool(false));
-        for child in extract_statements.iter(){
-          
-          result = self.run(child);
-        };
-
-        return result
-
+      Node::IfStatements{children} => match self.block(children, false)? {
+        Some(Tail::Value(value)) => Ok(value),
+        Some(Tail::Call(name, args)) => self.call_function(name, args),
+        //a block without a return evaluates to null
+        None => Ok(Value::Null),
       },
 
 
@@ -194,215 +951,47 @@ impl Runtime {
       // If the expression is not valid, the code returns an error message.
       Node::MathExpression{name, children} => {
         
-        let lhs = self.run(&children[0]);
-        let rhs = self.run(&children[1]);
-
-
-
-        let result = match (lhs,rhs) {
-          (Ok(Value::Number(value1)), Ok(Value::Number(value2))) => match name.as_str() {
-            "+" => Ok(Value::Number(value1 + value2)),
-            "-" => Ok(Value::Number(value1 - value2)),
-            "*" => Ok(Value::Number(value1 * value2)),
-            "/" => Ok(Value::Number(value1 / value2)),
-            "^" => {
-                    let mut exp_result = 1;
-                        for i in 0..value2 {
-                            exp_result = exp_result * value1;
-                        }
-                        Ok(Value::Number(exp_result))
-            },
-            _ => Err("Unimplemented math exp")
-        },
-          _=> {Err("Unimplemeneted math exp")}
-        };
-
-        result
+        let lhs = self.operand(&children[0])?;
+        let rhs = self.operand(&children[1])?;
+        math(name, lhs, rhs)
         
       },
       Node::ConditionalExpression{name, children} => {
         
-        let lhs = self.run(&children[0]);
-        let rhs = self.run(&children[1]);
-        // two possibilites of lhs and rhs
-        // lhs: bool & rhs: bool
-        // lhs: number(also id) & rhs: number(also id)
-        // the rest of the possibilites should result in an error
-
-
-        let result = match (lhs,rhs) {
-          (Ok(Value::Number(value1)), Ok(Value::Number(value2))) => match name.as_str() {
-            ">" => Ok(Value::Bool(value1 > value2)),
-            "<" => Ok(Value::Bool(value1 < value2)),
-            "<=" => Ok(Value::Bool(value1 <= value2)),
-            ">=" => Ok(Value::Bool(value1 >= value2)),
-            "==" => Ok(Value::Bool(value1 == value2)),
-            "!=" => Ok(Value::Bool(value1 != value2)),
-            _ => Err("Undefined Operator for numbers")
-        },
-        (Ok(Value::Bool(value1)), Ok(Value::Bool(value2))) => match name.as_str(){
-          "==" => Ok(Value::Bool(value1 == value2)),
-          "!=" => Ok(Value::Bool(value1 != value2)),
-            _ => Err("Undefined Operator for boolean")
-        },
-          _=> {Err("This Conditional expression is not possible")}
-        };
-
-        result
-        
-      },
-      // Defines a function that takes some arguments and executes a program based on those arguments. 
-      //The code first checks if the function exists, and if it does, it creates a new scope in which to execute the function's statements. 
-      //The code then executes each statement in the function's statements list and returns the result of the function's execution.
-      Node::FunctionCall{name, children} => {
-        let mut temp = Vec::new();
-        let mut func_statements = match self.functions.get(name){
-          Some(val) => val.to_owned(),
-          None => temp,
-         };
-
-         if (func_statements.is_empty()){
-          return Err("Undefined function");
-         }
-
-         
-
-
-        let mut func_call_args = Vec::new();
-        let mut counter = 0;
-         //This extracts funcargs node from children in func call
-         for args in children{
-          match args {
-             Node::FunctionArguments{children} => {
-              func_call_args = Self::extract_val(&args);
-              
-            },
-            _=> ()
-
-          }
-        }  
-
-        // val will contain a vec of Values, which correspond to the arguments passed in. 
-        //This is done to avoid any stack that go out of frame, especially for evaluating ID nodes w/ run method
-        let mut val = Vec::new();
-        for i in func_call_args.iter_mut(){
-          let x = self.run(i);
-          match x {
-            Ok(correct) => val.push(correct),
-            Err(incorrect) => ()
-          }
-        };
-
-
-
-        //returns false if there is nothing returned
-        let mut result = Ok(Value::Bool(false));
-        self.stack.push(HashMap::new());
-
-          for statement in func_statements.iter_mut(){
-          result = match statement{
-
-            Node::Identifier{value} => {
-               
-              let mut x = match self.stack.last_mut(){
-                Some(n) => n.insert(value.to_string(),val[counter].to_owned()),
-                None => {return Err("no scope has been init");}
-               };
-               Ok(Value::Bool(true))
-            },
-            Node::Statement{children} => self.run(statement),
-             Node::FunctionReturn{children} => {
-                self.run(&Node::FunctionReturn{children: children.to_vec()})
-              },
-            _=> Err("node is not a variableDefine or functionReturn")
-          };
-          counter +=1;
-            
-         };
-        
-              
-         result
+        let lhs = self.operand(&children[0])?;
+        let rhs = self.operand(&children[1])?;
+        compare(name, lhs, rhs)
         
       },
+      // Calls a function of the script, or a host function if the script does not define one with that name.
+      //The arguments are evaluated in the current frame before the call, see call_function for running the body.
+      Node::FunctionCall{name, children, ..} => self.call(name, children),
       // Defines a new function based on the elements in the children argument. 
       //The name of the function is retrieved from the first element of the children, 
       //and the statements that define the function are retrieved from rest of the children (head/tail). 
       //A new key-value pair is then inserted into the functions field of the current runtime object. 
       //If the function was successfully defined, the code returns a Value object with a boolean value of true, otherwise an error is returned.
-      Node::FunctionDefine{children} => {
-        let node_id = children[0].to_owned();
-        let var_name = match node_id{
-          Node::Identifier{value} => {value},
-          _=> {"error not ID".to_string()}
-        };
-
-        let mut x = Vec::new();
-        for statements in children{
-          match statements {
-             Node::FunctionArguments{children} => {
-              let mut args_id = Self::extract_val(&Node::FunctionArguments{children: children.to_owned()});
-
-              x.append(&mut args_id)
-            },
-            Node::Statement{children} => x.push(Node::Statement{children: children.to_owned()}),
-            _=> ()
-
-        }
-      }
-
-      if (x.len() == 0){
-        return Err("Incorrectly defined function")
-      }
-
-        self.functions.insert(
-          var_name,
-          x
-        );
-
-        Ok(Value::Bool(true))
-
-      },
+      Node::FunctionDefine{children, ..} => self.define_function(children),
       // Calls the run method on the first element in the children argument, 
       //which recursively evaluates the AST of the program being executed and returns the resulting value or error message.
       Node::FunctionReturn{children} => {
-        let result = self.run(&children[0]);
-       
-        self.stack.pop();
-        result
+        self.run(&children[0])
       },
 
       // Retrieves the value of a variable from the current frame on the stack. If the variable is defined in the current frame, 
       //the code returns its value. If the variable is not defined in the current frame, the code returns an error message.
-      Node::Identifier{value} => {
-       
-        //expect() is similar to an unwrap. Unwrap  allows program to send an return value or send an error
-        // depending if Result is Ok() or Err(),, if no hash map is found (no current stack available)
-        //however, expect allows for an additional panic error message to appear if Err()
-        let  result = match self.stack.last_mut(){
-          Some(n) => n.get(value),
-          None => {return Err("Undefined var");}
-         };
-
-        let ret_result = match result {
-          Some(val) => Ok(val.to_owned()),
-          None => Err("Undefined variable"),
-        };
-
-        ret_result
-
-
-      },
+      Node::Identifier{value, ..} => self.variable(value),
 
       // Checks the type of the first element in the children argument and deciding what to do based on that type.
       // If the type is a VariableDefine or FunctionReturn node, the code runs the run method on that node and returns the result.
-      Node::Statement{children} => {
+      Node::Statement{children, span} => {
+        self.debug(*span)?;
         //will equal statement{w/ children varDefine or funcReturn}
-        let node_first = children[0].to_owned();
+        let node_first = &children[0];
         //check if children is varDefine or funcReturn, then do run method on the valid node, which returns a result type
         let result = match node_first{
-          Node::VariableDefine{children} => self.run(&Node::VariableDefine{children}),
-          Node::FunctionReturn{children} => self.run(&Node::FunctionReturn{children}),
-          _=> Err("node is not a variableDefine or functionReturn")
+          Node::VariableDefine{..} | Node::FunctionReturn{..} | Node::IfExpression{..} | Node::ExpressionStatement{..} => self.run(node_first),
+          _=> Err("node is not a variableDefine, functionReturn, ifExpression or expressionStatement")
         };
 
         result
@@ -411,42 +1000,10 @@ impl Runtime {
       // Defines a new variable by assigning a name and a value to it. The name is retrieved from the first element of the children argument, 
       //and the value is retrieved by running the run method on the second element of the children argument. 
       //The key-value pair is then inserted into the last frame on the stack field of the current runtime object.
-      Node::VariableDefine{children} => {
-        //this gets the name of the var
-        let node_id = children[0].to_owned();
-        let var_name = match node_id{
-          Node::Identifier{value} => {value},
-          _=> {"error not ID".to_string()}
-        };
-
-        if var_name == "error not ID".to_string(){
-          return Err("Not valid ID (variable define)")
-        }
-
-        //this gets the value of the var
-        let var_val = self.run(&children[1]);
-        let result = match var_val {
-          Ok(Value) =>{
-            Value
-          },
-          _=> { return Err("Undefined function");
-          }
-        };
-
-
-
-        let ret_result = result.clone();
-
-
-        //will return Option<T> // some or none
-       let mut x = match self.stack.last_mut(){
-        Some(n) => n.insert(var_name,result),
-        None => {return Err("no scope has been init");}
-       };
-
-       
-        Ok(ret_result)
-
+      Node::VariableDefine{children} => self.define_variable(children),
+      //an expression run for its side effects, e.g. calling a host function, the caller ignores its value
+      Node::ExpressionStatement{children} => {
+        self.run(&children[0])
       },
       //for expression we want to return the children value of type: vec![nodes]
       Node::Expression{children} => {
@@ -466,20 +1023,313 @@ impl Runtime {
       Node::Bool{value} => {
         Ok(Value::Bool(*value))
       },
+      Node::Null => {
+        Ok(Value::Null)
+      },
+      //the right side is only evaluated when the left side is null
+      Node::CoalesceExpression{children} => {
+        match self.run(&children[0])? {
+          Value::Null => self.run(&children[1]),
+          value => Ok(value),
+        }
+      },
       x => {
         Err("Unimplemented 2")
       },
     }
   }
 
+  //collects the functions of a program and then evaluates its global let/const bindings
+  fn program(&mut self, children: &[Node]) -> Result<Value, &'static str> {
+    // the children in program consist of funcdefinitons and global let/const statements (look at grammar)
+    //functions are collected first so that global initializers are able to call them
+    let mut globals = Vec::new();
+    for child in children {
+      
+      match child {
+        
+        Node::FunctionDefine{..} => {
+          self.run(child)?;
+        },
+        //this is only if program is just an expression
+        Node::Expression{..} => {
+          self.functions.insert("main".to_string(), Rc::new(vec![Node::FunctionReturn{children: vec![child.clone()]}]));
+        },
+        //global let/const, these are evaluated in order once every function is known
+        Node::Statement{children, ..} => {
+          globals.push(&children[0]);
+        },
+        //imports are resolved by load_module before the program is run
+        Node::Import{..} => (),
+        Node::Comment{..} => (),
+        //use brings a function of an imported module into this module's namespace
+        Node::Use{path} => {
+          let local_name = match path.rfind("::") {
+            Some(index) => &path[index + 2..],
+            None => return Err("use needs a module path"),
+          };
+          if !self.functions.contains_key(path) {
+            return Err("Undefined function in use");
+          }
+          let alias = self.qualify(local_name);
+          self.aliases.insert(alias, path.to_string());
+        },
+
+        x => {return Err("Unimplemeneted 1");},
+      }
+    }
+
+    for global in globals {
+      self.define_global(global)?;
+    }
+    Ok(Value::Bool(true))
+  }
+
+  //evaluates the arguments of a call and makes it
+  fn call(&mut self, name: &str, children: &[Node]) -> Result<Value, &'static str> {
+    let qualified_name = self.resolve_function(name);
+    let defined = self.functions.contains_key(&qualified_name);
+    let host_function = self.host_functions.get(name).copied();
+    if !defined && host_function.is_none(){
+      return Err("Undefined function");
+    }
+
+    // val will contain a vec of Values, which correspond to the arguments passed in. 
+    //This is done to avoid any stack that go out of frame, especially for evaluating ID nodes w/ run method
+    let val = self.arguments(children)?;
+
+    match host_function {
+      Some(host_function) if !defined => {
+        //a host function like format can build a value bigger than the memory limit allows
+        let value = host_function(self, &val)?;
+        self.fits(value.size())?;
+        Ok(value)
+      },
+      _ => self.call_function(qualified_name, val),
+    }
+  }
+
+  //stores the parameters, return type and statements of a function under its qualified name
+  fn define_function(&mut self, children: &[Node]) -> Result<Value, &'static str> {
+    let node_id = children[0].to_owned();
+    let var_name = match node_id{
+      Node::Identifier{value, ..} => {value},
+      _=> {"error not ID".to_string()}
+    };
+
+    let mut x = Vec::new();
+    for statements in children{
+      match statements {
+        Node::FunctionArguments{children} => {
+          let mut args_id = Self::extract_val(&Node::FunctionArguments{children: children.to_owned()});
+
+          x.append(&mut args_id)
+        },
+        Node::Statement{..} => x.push(statements.clone()),
+        //the return type annotation
+        Node::Type{..} => x.push(statements.clone()),
+        _=> ()
+      }
+    }
+
+    if (x.len() == 0){
+      return Err("Incorrectly defined function")
+    }
+
+    self.functions.insert(
+      self.qualify(&var_name),
+      Rc::new(x)
+    );
+
+    Ok(Value::Bool(true))
+  }
+
+  //the value of a variable of the current frame, or of a global
+  fn variable(&mut self, value: &str) -> Result<Value, &'static str> {
+    //expect() is similar to an unwrap. Unwrap  allows program to send an return value or send an error
+    // depending if Result is Ok() or Err(),, if no hash map is found (no current stack available)
+    //however, expect allows for an additional panic error message to appear if Err()
+    let global_name = self.qualify(value);
+    let  result = match self.stack.last_mut(){
+      Some(n) => n.get(value),
+      None => {return Err("Undefined var");}
+    };
+
+    //variables in the current frame shadow globals
+    let ret_result = match result {
+      Some(val) => Ok(val.to_owned()),
+      None => match self.globals.get(&global_name) {
+        Some(val) => Ok(val.to_owned()),
+        None => Err("Undefined variable"),
+      },
+    };
+
+    ret_result
+  }
+
+  //evaluates a let and binds its value in the current frame
+  fn define_variable(&mut self, children: &[Node]) -> Result<Value, &'static str> {
+    //this gets the name of the var
+    let node_id = children[0].to_owned();
+    let var_name = match node_id{
+      Node::Identifier{value, ..} => {value},
+      _=> {"error not ID".to_string()}
+    };
+
+    if var_name == "error not ID".to_string(){
+      return Err("Not valid ID (variable define)")
+    }
+
+    if self.constants.contains(&self.qualify(&var_name)){
+      return Err("Cannot reassign constant")
+    }
+
+    //this gets the value of the var
+    let var_val = Self::check_annotation(self.run(&children[1]), children.get(2), "Value does not match its type annotation");
+    let result = match var_val {
+      Ok(value) =>{
+        value
+      },
+      Err(error)=> { return Err(error);
+      }
+    };
+
+
+
+    let ret_result = result.clone();
+
+
+    //will return Option<T> // some or none
+    //the value counts towards the memory limit while it is in the frame
+    self.bind(&var_name, result)?;
+
+    Ok(ret_result)
+  }
+
+}
+
+// Evaluates a math operator on the results of its operands.
+//Shared by the tree walker and the bytecode VM so both give the same results, a failed operand fails the whole expression.
+pub(crate) fn math(name: &str, lhs: Result<Value, &'static str>, rhs: Result<Value, &'static str>) -> Result<Value, &'static str> {
+  match (lhs, rhs) {
+    (Ok(Value::Number(value1)), Ok(Value::Number(value2))) => match name {
+      "+" => value1.checked_add(value2).map(Value::Number).ok_or("Number overflow"),
+      "-" => value1.checked_sub(value2).map(Value::Number).ok_or("Number overflow"),
+      "*" => value1.checked_mul(value2).map(Value::Number).ok_or("Number overflow"),
+      "/" if value2 == 0 => Err("Division by zero"),
+      "/" => value1.checked_div(value2).map(Value::Number).ok_or("Number overflow"),
+      //a negative exponent gives 1 like an empty product, checked_pow squares so a huge exponent takes no time
+      "^" if value2 < 0 => Ok(Value::Number(1)),
+      "^" => value1.checked_pow(value2 as u32).map(Value::Number).ok_or("Number overflow"),
+      _ => Err("Unimplemented math exp"),
+    },
+    _ => Err("Unimplemeneted math exp"),
+  }
+}
+
+// Evaluates a comparison on the results of its operands, shared like math.
+pub(crate) fn compare(name: &str, lhs: Result<Value, &'static str>, rhs: Result<Value, &'static str>) -> Result<Value, &'static str> {
+  // two possibilites of lhs and rhs
+  // lhs: bool & rhs: bool
+  // lhs: number(also id) & rhs: number(also id)
+  // the rest of the possibilites should result in an error
+  match (lhs, rhs) {
+    (Ok(Value::Number(value1)), Ok(Value::Number(value2))) => match name {
+      ">" => Ok(Value::Bool(value1 > value2)),
+      "<" => Ok(Value::Bool(value1 < value2)),
+      "<=" => Ok(Value::Bool(value1 <= value2)),
+      ">=" => Ok(Value::Bool(value1 >= value2)),
+      "==" => Ok(Value::Bool(value1 == value2)),
+      "!=" => Ok(Value::Bool(value1 != value2)),
+      _ => Err("Undefined Operator for numbers"),
+    },
+    //null is only equal to null
+    (Ok(value1), Ok(value2)) if value1 == Value::Null || value2 == Value::Null => match name {
+      "==" => Ok(Value::Bool(value1 == value2)),
+      "!=" => Ok(Value::Bool(value1 != value2)),
+      _ => Err("Undefined Operator for null"),
+    },
+    (Ok(Value::Bool(value1)), Ok(Value::Bool(value2))) => match name {
+      "==" => Ok(Value::Bool(value1 == value2)),
+      "!=" => Ok(Value::Bool(value1 != value2)),
+      _ => Err("Undefined Operator for boolean"),
+    },
+    _ => Err("This Conditional expression is not possible"),
+  }
 }
 
 pub fn start_interpreter(node: &Node) -> Result<Value, &'static str> {
   let mut runtime = Runtime::new();
-  //when we run this the first time, we are collecting all the function definitions into functions data strcutre
-  runtime.run(node);
+  runtime.start(node)
+}
+
+// is_null(value) is true only for null
+fn is_null(_runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  match args {
+    [value] => Ok(Value::Bool(*value == Value::Null)),
+    _ => Err("is_null takes one argument"),
+  }
+}
+
+// print(a, b, ...) writes the arguments separated by spaces
+fn print(runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  let text: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+  runtime.write(&text.join(" "))?;
+  Ok(Value::Null)
+}
+
+// println(a, b, ...) is print followed by a newline
+fn println(runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  print(runtime, args)?;
+  runtime.write("\n")?;
+  Ok(Value::Null)
+}
+
+// format(template, a, b, ...) replaces each {} in template with the next argument, {{ and }} are literal braces
+fn format(_runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  let template = match args.first() {
+    Some(Value::String(template)) => template,
+    _ => return Err("format takes a string template"),
+  };
+
+  let mut values = args[1..].iter();
+  let mut result = String::new();
+  let mut chars = template.chars().peekable();
+  while let Some(c) = chars.next() {
+    match (c, chars.peek()) {
+      ('{', Some('{')) | ('}', Some('}')) => {
+        chars.next();
+        result.push(c);
+      },
+      ('{', Some('}')) => {
+        chars.next();
+        match values.next() {
+          Some(value) => result.push_str(&value.to_string()),
+          None => return Err("format has more {} than arguments"),
+        }
+      },
+      _ => result.push(c),
+    }
+  }
+
+  if values.next().is_some() {
+    return Err("format has more arguments than {}");
+  }
+  Ok(Value::String(result))
+}
+
+// assert(condition) stops the program with an error when condition is false
+fn assert(_runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  match args {
+    [Value::Bool(true)] => Ok(Value::Null),
+    [Value::Bool(false)] => Err("Assertion failed"),
+    _ => Err("assert takes one bool"),
+  }
+}
 
-  //this will begin the main program, by going to check if main is a function, and then going from there (goes into main function and checks for other func calls)
-  let start_main = Node::FunctionCall{name: "main".to_string(), children: vec![]};
-  runtime.run(&start_main)
+// Runs the main function of an .asa file, resolving its imports relative to the file.
+pub fn run_file(path: &Path) -> Result<Value, ModuleError> {
+  let mut runtime = Runtime::new();
+  runtime.load_file(path)?;
+  runtime.run_main().map_err(|error| runtime.runtime_error(error))
 }
@@ -1,7 +1,21 @@
 extern crate nom;
+extern crate serde;
+extern crate serde_json;
 
+pub mod checker;
+pub mod dap;
+pub mod debugger;
+pub mod formatter;
 pub mod interpreter;
+pub mod lint;
+pub mod lsp;
+pub mod module;
+pub mod optimizer;
 pub mod parser;
+mod protocol;
+pub mod resolver;
+pub mod vm;
 
-pub use self::parser::{program, Node};
-pub use self::interpreter::{start_interpreter, Value};
\ No newline at end of file
+pub use self::parser::{program, Node, Span};
+pub use self::interpreter::{run_file, start_interpreter, Debugger, Engine, InterruptHandle, Output, Runtime, Value};
+pub use self::module::ModuleError;
\ No newline at end of file
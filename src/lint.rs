@@ -0,0 +1,257 @@
+use crate::parser::{Node, Span};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+// Names of the rules, as used in the config file and in the output.
+pub const RULES: [&str; 5] = ["naming", "deep-if", "constant-condition", "redundant-comparison", "magic-number"];
+
+// Which rules run and their settings.
+//Every rule is on unless the config turns it off.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LintConfig {
+  disabled: HashSet<String>,
+  //the most else ifs an if may have before deep-if reports it
+  pub max_else_if: usize,
+}
+
+impl Default for LintConfig {
+  fn default() -> LintConfig {
+    LintConfig {
+      disabled: HashSet::new(),
+      max_else_if: 3,
+    }
+  }
+}
+
+impl LintConfig {
+  // Reads a config file made of "key = value" lines, # starts a comment.
+  //A rule name as key turns the rule on or off, e.g. "magic-number = off", and max-else-if sets the deep-if limit.
+  pub fn parse(text: &str) -> Result<LintConfig, String> {
+    let mut config = LintConfig::default();
+    for (number, line) in text.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+      let (key, value) = match line.find('=') {
+        Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+        None => return Err(format!("line {}: expected key = value", number + 1)),
+      };
+      match (key, value) {
+        ("max-else-if", value) => config.max_else_if = value.parse().map_err(|_| format!("line {}: max-else-if needs a number", number + 1))?,
+        (rule, "on") if RULES.contains(&rule) => { config.disabled.remove(rule); },
+        (rule, "off") if RULES.contains(&rule) => { config.disabled.insert(rule.to_string()); },
+        (rule, _) if RULES.contains(&rule) => return Err(format!("line {}: {} must be on or off", number + 1, rule)),
+        (key, _) => return Err(format!("line {}: unknown rule {}", number + 1, key)),
+      }
+    }
+    Ok(config)
+  }
+
+  pub fn is_enabled(&self, rule: &str) -> bool {
+    !self.disabled.contains(rule)
+  }
+
+  pub fn disable(&mut self, rule: &str) {
+    self.disabled.insert(rule.to_string());
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+  pub rule: &'static str,
+  pub message: String,
+  //the statement or function definition the lint is about
+  pub span: Span,
+}
+
+impl Lint {
+  // Formats the lint with the line and column it starts at, e.g. "3:3: magic-number: ...".
+  pub fn render(&self, source: &str) -> String {
+    let (line, column) = self.span.line_col(source);
+    format!("{}:{}: {}", line, column, self)
+  }
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.rule, self.message)
+  }
+}
+
+fn is_snake_case(name: &str) -> bool {
+  name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_upper_case(name: &str) -> bool {
+  name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_literal(node: &Node) -> bool {
+  matches!(node, Node::Number{..} | Node::Bool{..} | Node::String{..} | Node::Null)
+}
+
+struct Linter<'a> {
+  config: &'a LintConfig,
+  //the statement or function being linted, lints inside it are reported at its span
+  span: Span,
+  //numbers in a const initializer are what the magic-number rule asks for
+  in_const: bool,
+  lints: Vec<Lint>,
+}
+
+impl<'a> Linter<'a> {
+
+  fn report(&mut self, rule: &'static str, message: String) {
+    if self.config.is_enabled(rule) {
+      self.lints.push(Lint{rule, message, span: self.span});
+    }
+  }
+
+  //functions, variables and parameters are snake_case, constants may also be UPPER_CASE
+  fn name(&mut self, kind: &str, node: &Node) {
+    if let Node::Identifier{value, ..} = node {
+      let valid = is_snake_case(value) || (kind == "constant" && is_upper_case(value));
+      if !valid {
+        self.report("naming", format!("{} {} should be snake_case", kind, value));
+      }
+    }
+  }
+
+  fn function(&mut self, children: &[Node], span: Span) {
+    self.span = span;
+    self.name("function", &children[0]);
+    for child in &children[1..] {
+      match child {
+        Node::FunctionArguments{children} => {
+          for param in children {
+            self.name("parameter", &param.children()[0]);
+          }
+        },
+        Node::Statement{..} => self.statement(child),
+        _ => (),
+      }
+    }
+  }
+
+  fn statement(&mut self, node: &Node) {
+    let (children, span) = match node {
+      Node::Statement{children, span} => (children, *span),
+      _ => return,
+    };
+    let outer = std::mem::replace(&mut self.span, span);
+    match &children[0] {
+      Node::VariableDefine{children} => {
+        self.name("variable", &children[0]);
+        self.expression(&children[1]);
+      },
+      Node::ConstantDefine{children} => {
+        self.name("constant", &children[0]);
+        self.in_const = true;
+        self.expression(&children[1]);
+        self.in_const = false;
+      },
+      other => self.expression(other),
+    }
+    self.span = outer;
+  }
+
+  fn condition(&mut self, node: &Node) {
+    let constant = match node {
+      Node::Bool{..} => true,
+      Node::ConditionalExpression{children, ..} => is_literal(&children[0]) && is_literal(&children[1]),
+      _ => false,
+    };
+    if constant {
+      self.report("constant-condition", "the condition is always the same, the if is not needed".to_string());
+    }
+  }
+
+  fn expression(&mut self, node: &Node) {
+    match node {
+      Node::Number{value} => {
+        if !self.in_const && *value != 0 && *value != 1 {
+          self.report("magic-number", format!("magic number {}, name it with a const", value));
+        }
+      },
+      Node::IfExpression{children} => {
+        self.condition(&children[0]);
+        let else_ifs = children.iter().filter(|child| matches!(child, Node::ElseIfExpression{..})).count();
+        if else_ifs > self.config.max_else_if {
+          self.report("deep-if", format!("if has {} else ifs, more than {}", else_ifs, self.config.max_else_if));
+        }
+        for child in children {
+          match child {
+            Node::ElseIfExpression{children} => {
+              self.condition(&children[0]);
+              self.expression(&children[0]);
+              self.expression(&children[1]);
+            },
+            _ => self.expression(child),
+          }
+        }
+      },
+      //x == true and x != false are the same as x
+      Node::ConditionalExpression{name, children} => {
+        let redundant = match (name.as_str(), &children[0], &children[1]) {
+          ("==", Node::Bool{value: true}, other) | ("==", other, Node::Bool{value: true}) |
+          ("!=", Node::Bool{value: false}, other) | ("!=", other, Node::Bool{value: false}) => !matches!(other, Node::Bool{..}),
+          _ => false,
+        };
+        if redundant {
+          self.report("redundant-comparison", format!("comparing with a bool literal using {} is redundant, use the value itself", name));
+        }
+        for child in children {
+          self.expression(child);
+        }
+      },
+      Node::Statement{..} => self.statement(node),
+      _ => {
+        for child in node.children() {
+          self.expression(child);
+        }
+      },
+    }
+  }
+}
+
+// Runs the rules the config enables over a parsed program, the lints are in source order.
+pub fn lint(program: &Node, config: &LintConfig) -> Vec<Lint> {
+  let mut linter = Linter {
+    config,
+    span: Span::default(),
+    in_const: false,
+    lints: Vec::new(),
+  };
+  for child in program.children() {
+    match child {
+      Node::FunctionDefine{children, span} => linter.function(children, *span),
+      Node::Statement{..} => linter.statement(child),
+      _ => (),
+    }
+  }
+  let mut lints = linter.lints;
+  lints.sort_by_key(|lint| lint.span.start);
+  lints
+}
+
+// A lint as written by to_json.
+#[derive(Serialize)]
+struct JsonLint<'a> {
+  rule: &'a str,
+  message: &'a str,
+  line: usize,
+  column: usize,
+  start: usize,
+  end: usize,
+}
+
+// Formats the lints as a JSON array for editors, each lint has its rule, message, line, column and byte offsets.
+pub fn to_json(lints: &[Lint], source: &str) -> String {
+  let items: Vec<JsonLint> = lints.iter().map(|lint| {
+    let (line, column) = lint.span.line_col(source);
+    JsonLint{rule: lint.rule, message: &lint.message, line, column, start: lint.span.start, end: lint.span.end}
+  }).collect();
+  serde_json::to_string(&items).unwrap_or_default()
+}
@@ -0,0 +1,486 @@
+use crate::checker::{check, infer, Type, Types};
+use crate::formatter::format_source;
+use crate::parser::{program, Node, Span};
+use crate::protocol;
+use crate::resolver::{resolve, Severity, BUILTINS};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+// Words of the language, completion offers them with the names in scope.
+const KEYWORDS: [&str; 11] = ["fn", "let", "const", "return", "if", "else", "true", "false", "null", "import", "use"];
+
+// Kinds of completion items and document symbols, numbered as in the protocol.
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_KEYWORD: i64 = 14;
+const SYMBOL_FUNCTION: i64 = 12;
+const SYMBOL_VARIABLE: i64 = 13;
+const SYMBOL_CONSTANT: i64 = 14;
+
+fn is_word(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_'
+}
+
+//the position of a byte offset in source, lines and UTF-16 code units counted from 0 like the protocol does
+fn position(source: &str, offset: usize) -> Json {
+  let before = &source[..offset.min(source.len())];
+  let start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+  json!({"line": before.matches('\n').count(), "character": before[start..].encode_utf16().count()})
+}
+
+//the byte offset of a position of the protocol in source
+fn offset(source: &str, position: &Json) -> usize {
+  let line = position["line"].as_u64().unwrap_or(0);
+  let character = position["character"].as_u64().unwrap_or(0) as usize;
+  let mut start = 0;
+  for _ in 0..line {
+    match source[start..].find('\n') {
+      Some(index) => start += index + 1,
+      None => return source.len(),
+    }
+  }
+  let mut units = 0;
+  for (index, c) in source[start..].char_indices() {
+    if units >= character || c == '\n' {
+      return start + index;
+    }
+    units += c.len_utf16();
+  }
+  source.len()
+}
+
+fn range(source: &str, start: usize, end: usize) -> Json {
+  json!({"start": position(source, start), "end": position(source, end)})
+}
+
+//the identifier the offset is in or just after, with the offset it starts at
+fn word_at(source: &str, offset: usize) -> Option<(usize, &str)> {
+  let offset = offset.min(source.len());
+  let start = match source[..offset].char_indices().rev().find(|(_, c)| !is_word(*c)) {
+    Some((index, c)) => index + c.len_utf8(),
+    None => 0,
+  };
+  let end = source[offset..].find(|c| !is_word(c)).map(|index| offset + index).unwrap_or(source.len());
+  let word = &source[start..end];
+  if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+    return None;
+  }
+  Some((start, word))
+}
+
+//where name is first written as a whole word in the span, the nodes do not keep where their names are
+fn find_word(source: &str, span: Span, name: &str) -> usize {
+  let text = &source[span.start.min(source.len())..span.end.min(source.len())];
+  let mut searched = 0;
+  while let Some(index) = text[searched..].find(name) {
+    let start = searched + index;
+    let end = start + name.len();
+    let before = text[..start].chars().next_back().map(is_word).unwrap_or(false);
+    let after = text[end..].chars().next().map(is_word).unwrap_or(false);
+    if !before && !after {
+      return span.start + start;
+    }
+    searched = end;
+  }
+  span.start
+}
+
+fn name(node: &Node) -> Option<&str> {
+  match node {
+    Node::Identifier{value, ..} => Some(value),
+    _ => None,
+  }
+}
+
+// A function, global, parameter or let that can be used at a point of the document.
+struct Binding {
+  name: String,
+  //where the name is written in its definition
+  at: usize,
+  //the function it is defined in, "" for globals and functions
+  function: String,
+  //true for functions, which are only reached by calls
+  callable: bool,
+  constant: bool,
+}
+
+// A parsed document and what is known about it without running it.
+struct Document<'a> {
+  source: &'a str,
+  //what could be parsed, a program that does not parse to its end still has the items before the problem
+  tree: Node,
+  //where the text that could not be parsed starts
+  unparsed: Option<usize>,
+}
+
+impl<'a> Document<'a> {
+
+  fn new(source: &'a str) -> Document<'a> {
+    match program(source) {
+      Ok((rest, tree)) if rest.trim().is_empty() => Document{source, tree, unparsed: None},
+      Ok((rest, tree)) => Document{source, tree, unparsed: Some(source.len() - rest.len())},
+      Err(_) => Document{source, tree: Node::Program{children: Vec::new()}, unparsed: Some(0)},
+    }
+  }
+
+  fn items(&self) -> &[Node] {
+    self.tree.children()
+  }
+
+  //the function definition the offset is in, with its name
+  fn function_at(&self, offset: usize) -> Option<(&str, &[Node], Span)> {
+    self.items().iter().find_map(|item| match item {
+      Node::FunctionDefine{children, span} if span.start <= offset && offset <= span.end => {
+        name(&children[0]).map(|name| (name, children.as_slice(), *span))
+      },
+      _ => None,
+    })
+  }
+
+  //a let or const statement as a binding of function
+  fn binding(&self, statement: &Node, function: &str) -> Option<Binding> {
+    let (children, span) = match statement {
+      Node::Statement{children, span} => (children, *span),
+      _ => return None,
+    };
+    let (constant, children) = match &children[0] {
+      Node::VariableDefine{children} => (false, children),
+      Node::ConstantDefine{children} => (true, children),
+      _ => return None,
+    };
+    let name = name(&children[0])?;
+    Some(Binding{name: name.to_string(), at: find_word(self.source, span, name), function: function.to_string(), callable: false, constant})
+  }
+
+  //adds the lets of a block defined before offset, and of the if blocks around offset
+  fn block_scope(&self, statements: &[Node], offset: usize, function: &str, bindings: &mut Vec<Binding>) {
+    for statement in statements {
+      let (children, span) = match statement {
+        Node::Statement{children, span} => (children, *span),
+        _ => continue,
+      };
+      if span.end <= offset {
+        bindings.extend(self.binding(statement, function));
+      } else if span.start <= offset {
+        let mut blocks = Vec::new();
+        if_blocks(&children[0], &mut blocks);
+        for block in blocks {
+          let spans: Vec<Span> = block.children().iter().filter_map(Node::span).collect();
+          if let (Some(first), Some(last)) = (spans.first(), spans.last()) {
+            if first.start <= offset && offset <= last.end {
+              self.block_scope(block.children(), offset, function, bindings);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  // The names that can be used at offset, a later binding with the same name shadows an earlier one.
+  fn visible(&self, offset: usize) -> Vec<Binding> {
+    let function = self.function_at(offset);
+    let mut bindings = Vec::new();
+    for item in self.items() {
+      match item {
+        Node::FunctionDefine{children, span} => if let Some(name) = name(&children[0]) {
+          bindings.push(Binding{name: name.to_string(), at: find_word(self.source, *span, name), function: String::new(), callable: true, constant: false});
+        },
+        //functions run once every global is defined, a global initializer only sees the globals before it
+        Node::Statement{span, ..} if function.is_some() || span.end <= offset => bindings.extend(self.binding(item, "")),
+        _ => (),
+      }
+    }
+
+    if let Some((function, children, span)) = function {
+      //the parameters are written after the name, in the parentheses
+      let header = Span{start: span.start + self.source[span.start..span.end].find('(').unwrap_or(0), end: span.end};
+      for child in children {
+        if let Node::FunctionArguments{children} = child {
+          for param in children {
+            if let Some(name) = param.children().first().and_then(name) {
+              bindings.push(Binding{name: name.to_string(), at: find_word(self.source, header, name), function: function.to_string(), callable: false, constant: false});
+            }
+          }
+        }
+      }
+      self.block_scope(children, offset, function, &mut bindings);
+    }
+    bindings
+  }
+
+  // Where the name at offset is defined. A name followed by ( is a call and is looked up among the functions.
+  fn definition(&self, offset: usize) -> Option<usize> {
+    let (start, word) = word_at(self.source, offset)?;
+    let call = self.source[start + word.len()..].trim_start().starts_with('(');
+    self.visible(offset).into_iter().rev()
+      .find(|binding| binding.name == word && binding.callable == call)
+      .map(|binding| binding.at)
+  }
+
+  //how a function is shown, e.g. fn add(a: int, b) -> int
+  fn signature(&self, function: &str, types: &Types) -> String {
+    let children = self.items().iter().find_map(|item| match item {
+      Node::FunctionDefine{children, ..} if name(&children[0]) == Some(function) => Some(children),
+      _ => None,
+    });
+    let children = match children {
+      Some(children) => children,
+      None => return format!("fn {}(...)", function),
+    };
+    let mut params = Vec::new();
+    for child in children.iter() {
+      if let Node::FunctionArguments{children} = child {
+        for param in children {
+          match param.children() {
+            [Node::Identifier{value, ..}, Node::Type{name}] => params.push(format!("{}: {}", value, name)),
+            [Node::Identifier{value, ..}] => params.push(value.to_string()),
+            _ => (),
+          }
+        }
+      }
+    }
+    let returns = types.functions.get(function).copied().unwrap_or(Type::Unknown);
+    format!("fn {}({}) -> {}", function, params.join(", "), returns)
+  }
+
+  // The inferred type of the name at offset, or the signature of the function it calls or defines.
+  fn hover(&self, offset: usize) -> Option<Json> {
+    let (start, word) = word_at(self.source, offset)?;
+    if KEYWORDS.contains(&word) {
+      return None;
+    }
+    let types = infer(&self.tree);
+    let call = self.source[start + word.len()..].trim_start().starts_with('(');
+    let function = self.function_at(offset).map(|(name, _, _)| name).unwrap_or("");
+    let shown = if call || self.source[..start].trim_end().ends_with("fn") {
+      self.signature(word, &types)
+    } else {
+      let t = types.variables.get(&(function.to_string(), word.to_string()))
+        .or_else(|| types.variables.get(&(String::new(), word.to_string())))?;
+      format!("{}: {}", word, t)
+    };
+    Some(json!({
+      "contents": {"kind": "markdown", "value": format!("```asalang\n{}\n```", shown)},
+      "range": range(self.source, start, start + word.len()),
+    }))
+  }
+
+  // Problems found by the parser, or if the whole document parses, by the resolver and the type checker.
+  fn diagnostics(&self) -> Vec<Json> {
+    if let Some(unparsed) = self.unparsed {
+      let end = self.source[unparsed..].find('\n').map(|index| unparsed + index).unwrap_or(self.source.len());
+      return vec![json!({
+        "range": range(self.source, unparsed, end),
+        "severity": 1,
+        "source": "asalang",
+        "message": "could not parse this",
+      })];
+    }
+
+    let mut diagnostics: Vec<Json> = resolve(&self.tree, &[]).iter().map(|diagnostic| json!({
+      "range": range(self.source, diagnostic.span.start, diagnostic.span.end),
+      "severity": if diagnostic.severity == Severity::Error { 1 } else { 2 },
+      "source": "asalang",
+      "message": diagnostic.message,
+    })).collect();
+    for error in check(&self.tree) {
+      diagnostics.push(json!({
+        "range": range(self.source, error.span.start, error.span.end),
+        "severity": 1,
+        "source": "asalang",
+        "message": error.to_string(),
+      }));
+    }
+    diagnostics
+  }
+
+  // The functions and globals of the document, functions have their lets as children.
+  fn symbols(&self) -> Vec<Json> {
+    let symbol = |binding: &Binding, span: Span, kind: i64, children: Vec<Json>| json!({
+      "name": binding.name,
+      "kind": kind,
+      "range": range(self.source, span.start, span.end),
+      "selectionRange": range(self.source, binding.at, binding.at + binding.name.len()),
+      "children": children,
+    });
+    let mut symbols = Vec::new();
+    for item in self.items() {
+      match item {
+        Node::FunctionDefine{children, span} => {
+          let function = match name(&children[0]) {
+            Some(function) => function,
+            None => continue,
+          };
+          let mut lets = Vec::new();
+          collect_lets(children, &mut lets);
+          let lets = lets.into_iter().filter_map(|statement| {
+            let binding = self.binding(statement, function)?;
+            let kind = if binding.constant { SYMBOL_CONSTANT } else { SYMBOL_VARIABLE };
+            Some(symbol(&binding, statement.span().unwrap_or_default(), kind, Vec::new()))
+          }).collect();
+          let binding = Binding{name: function.to_string(), at: find_word(self.source, *span, function), function: String::new(), callable: true, constant: false};
+          symbols.push(symbol(&binding, *span, SYMBOL_FUNCTION, lets));
+        },
+        Node::Statement{span, ..} => if let Some(binding) = self.binding(item, "") {
+          let kind = if binding.constant { SYMBOL_CONSTANT } else { SYMBOL_VARIABLE };
+          symbols.push(symbol(&binding, *span, kind, Vec::new()));
+        },
+        _ => (),
+      }
+    }
+    symbols
+  }
+
+  // The names in scope at offset, then the built-in functions and the keywords.
+  fn completion(&self, offset: usize) -> Vec<Json> {
+    let types = infer(&self.tree);
+    let mut items: Vec<Json> = Vec::new();
+    let mut seen = Vec::new();
+    for binding in self.visible(offset).iter().rev() {
+      if seen.contains(&binding.name) {
+        continue;
+      }
+      seen.push(binding.name.clone());
+      let (kind, detail) = if binding.callable {
+        (COMPLETION_FUNCTION, self.signature(&binding.name, &types))
+      } else {
+        let t = types.variables.get(&(binding.function.clone(), binding.name.clone())).copied().unwrap_or(Type::Unknown);
+        (COMPLETION_VARIABLE, t.to_string())
+      };
+      items.push(json!({"label": binding.name, "kind": kind, "detail": detail}));
+    }
+    for builtin in BUILTINS.iter() {
+      items.push(json!({"label": builtin, "kind": COMPLETION_FUNCTION, "detail": "built-in function"}));
+    }
+    for keyword in KEYWORDS.iter() {
+      items.push(json!({"label": keyword, "kind": COMPLETION_KEYWORD}));
+    }
+    items
+  }
+
+  // The whole document in the canonical layout as a single edit, None if it does not parse.
+  fn formatting(&self) -> Option<Json> {
+    let formatted = format_source(self.source).ok()?;
+    Some(json!([{"range": range(self.source, 0, self.source.len()), "newText": formatted}]))
+  }
+}
+
+//the if blocks in a node that are not inside another if block
+fn if_blocks<'a>(node: &'a Node, blocks: &mut Vec<&'a Node>) {
+  if let Node::IfStatements{..} = node {
+    blocks.push(node);
+    return;
+  }
+  for child in node.children() {
+    if_blocks(child, blocks);
+  }
+}
+
+//the let and const statements in the nodes, including the ones in if blocks
+fn collect_lets<'a>(nodes: &'a [Node], lets: &mut Vec<&'a Node>) {
+  for node in nodes {
+    if let Node::Statement{children, ..} = node {
+      if let Node::VariableDefine{..} | Node::ConstantDefine{..} = &children[0] {
+        lets.push(node);
+      }
+    }
+    collect_lets(node.children(), lets);
+  }
+}
+
+//the result of a request about a document, None if the method is not one the server knows
+fn answer(method: &str, params: &Json, documents: &HashMap<String, String>) -> Option<Json> {
+  let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+  let source = match documents.get(uri) {
+    Some(source) => source,
+    //a document that was never opened has nothing to show
+    None => return match method {
+      "textDocument/definition" | "textDocument/hover" | "textDocument/formatting" => Some(Json::Null),
+      "textDocument/documentSymbol" | "textDocument/completion" => Some(json!([])),
+      _ => None,
+    },
+  };
+  let document = Document::new(source);
+  let at = offset(source, &params["position"]);
+  let result = match method {
+    "textDocument/definition" => match document.definition(at) {
+      Some(start) => {
+        let length = word_at(source, start).map(|(_, word)| word.len()).unwrap_or(0);
+        json!({"uri": uri, "range": range(source, start, start + length)})
+      },
+      None => Json::Null,
+    },
+    "textDocument/hover" => document.hover(at).unwrap_or(Json::Null),
+    "textDocument/documentSymbol" => json!(document.symbols()),
+    "textDocument/completion" => json!(document.completion(at)),
+    "textDocument/formatting" => document.formatting().unwrap_or(Json::Null),
+    _ => return None,
+  };
+  Some(result)
+}
+
+fn publish(output: &mut dyn Write, uri: &str, diagnostics: Vec<Json>) {
+  protocol::write(output, &json!({
+    "jsonrpc": "2.0",
+    "method": "textDocument/publishDiagnostics",
+    "params": {"uri": uri, "diagnostics": diagnostics},
+  }));
+}
+
+// Serves the Language Server Protocol on input and output until the client sends exit, as asalang lsp does on stdio.
+//Documents are synced in full, their diagnostics are published when they are opened or changed. Definitions,
+//hovers, document symbols, completion and formatting are answered from the text the client last sent.
+pub fn serve(mut input: Box<dyn BufRead>, mut output: Box<dyn Write>) {
+  let mut documents: HashMap<String, String> = HashMap::new();
+  while let Some(message) = protocol::read(&mut input) {
+    let method = message["method"].as_str().unwrap_or("");
+    let params = &message["params"];
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+    let result = match method {
+      "initialize" => Some(json!({
+        "capabilities": {
+          "textDocumentSync": 1,
+          "definitionProvider": true,
+          "hoverProvider": true,
+          "documentSymbolProvider": true,
+          "completionProvider": {},
+          "documentFormattingProvider": true,
+        },
+        "serverInfo": {"name": "asalang"},
+      })),
+      "shutdown" => Some(Json::Null),
+      "exit" => return,
+      "textDocument/didOpen" | "textDocument/didChange" => {
+        //with full sync the last change is the whole text
+        let text = match method {
+          "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+          _ => params["contentChanges"].as_array().and_then(|changes| changes.last()).and_then(|change| change["text"].as_str()),
+        };
+        if let Some(text) = text {
+          let diagnostics = Document::new(text).diagnostics();
+          documents.insert(uri.clone(), text.to_string());
+          publish(&mut output, &uri, diagnostics);
+        }
+        None
+      },
+      "textDocument/didClose" => {
+        documents.remove(&uri);
+        publish(&mut output, &uri, Vec::new());
+        None
+      },
+      _ => answer(method, params, &documents),
+    };
+
+    //notifications have no id and get no response
+    let id = match message.get("id") {
+      Some(id) => id,
+      None => continue,
+    };
+    let response = match result {
+      Some(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+      None => json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": format!("{} is not supported", method)}}),
+    };
+    protocol::write(&mut output, &response);
+  }
+}
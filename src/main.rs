@@ -1,9 +1,292 @@
 extern crate nom;
 extern crate asalang;
+extern crate serde_json;
 
-use asalang::{program, Value, start_interpreter, Node};
+use asalang::{program, start_interpreter, Engine, Runtime};
+use asalang::checker::check;
+use asalang::resolver::{resolve, Severity};
+use asalang::lint::{lint, to_json, LintConfig};
+use asalang::formatter::format_source;
+use asalang::optimizer::optimize;
+use asalang::debugger::Console;
+use std::path::Path;
+
+// asalang lint [--json] [--config file] file.asa
+//The config is read from --config, or from .asalint next to the file if there is one.
+//Exits with 1 when there are lints, so the command can be used in scripts.
+fn lint_command(args: &[String]) -> i32 {
+  let mut json = false;
+  let mut config_path = None;
+  let mut path = None;
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--json" => json = true,
+      "--config" => config_path = args.next().map(|arg| arg.to_string()),
+      _ => path = Some(arg.to_string()),
+    }
+  }
+  let path = match path {
+    Some(path) => path,
+    None => {
+      println!("usage: asalang lint [--json] [--config file] file.asa");
+      return 2;
+    },
+  };
+
+  let config_path = config_path.or_else(|| {
+    let default = Path::new(&path).with_file_name(".asalint");
+    if default.exists() { Some(default.to_string_lossy().to_string()) } else { None }
+  });
+  let config = match config_path {
+    Some(config_path) => {
+      let text = match std::fs::read_to_string(&config_path) {
+        Ok(text) => text,
+        Err(_) => {
+          println!("ERROR could not read {}", config_path);
+          return 2;
+        },
+      };
+      match LintConfig::parse(&text) {
+        Ok(config) => config,
+        Err(error) => {
+          println!("ERROR {}: {}", config_path, error);
+          return 2;
+        },
+      }
+    },
+    None => LintConfig::default(),
+  };
+
+  let source = match std::fs::read_to_string(&path) {
+    Ok(source) => source,
+    Err(_) => {
+      println!("ERROR could not read {}", path);
+      return 2;
+    },
+  };
+  //a file that does not parse to its end would only be linted up to the problem
+  let tree = match program(&source) {
+    Ok((unparsed, tree)) if unparsed.trim().is_empty() => tree,
+    Ok((unparsed, _)) => {
+      println!("ERROR {}: unparsed text {:?}", path, unparsed);
+      return 2;
+    },
+    Err(error) => {
+      println!("ERROR {}: {:?}", path, error);
+      return 2;
+    },
+  };
+
+  let lints = lint(&tree, &config);
+  if json {
+    println!("{}", to_json(&lints, &source));
+  } else {
+    for found in &lints {
+      println!("{}:{}", path, found.render(&source));
+    }
+  }
+  if lints.is_empty() { 0 } else { 1 }
+}
+
+// asalang fmt [--check] file.asa...
+//Rewrites each file with the canonical layout. With --check nothing is written, the files that are not
+//formatted are listed and the exit code is 1 if there are any. A file with a comment the layout would drop
+//is left as it is and reported, the exit code is 2.
+fn fmt_command(args: &[String]) -> i32 {
+  let check = args.iter().any(|arg| arg == "--check");
+  let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+  if paths.is_empty() {
+    println!("usage: asalang fmt [--check] file.asa...");
+    return 2;
+  }
+
+  let mut code = 0;
+  for path in paths {
+    let source = match std::fs::read_to_string(path) {
+      Ok(source) => source,
+      Err(_) => {
+        println!("ERROR could not read {}", path);
+        return 2;
+      },
+    };
+    let formatted = match format_source(&source) {
+      Ok(formatted) => formatted,
+      Err(error) => {
+        println!("ERROR {}: {}", path, error);
+        return 2;
+      },
+    };
+    if formatted == source {
+      continue;
+    }
+    if check {
+      println!("{} is not formatted", path);
+      code = 1;
+    } else if std::fs::write(path, formatted).is_err() {
+      println!("ERROR could not write {}", path);
+      return 2;
+    }
+  }
+  code
+}
+
+// asalang parse [--json] [--optimize] file.asa
+//Prints the parse tree of the file, as JSON for other tools with --json, after the optimizer has run with --optimize.
+fn parse_command(args: &[String]) -> i32 {
+  let json = args.iter().any(|arg| arg == "--json");
+  let optimized = args.iter().any(|arg| arg == "--optimize");
+  let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+    Some(path) => path,
+    None => {
+      println!("usage: asalang parse [--json] [--optimize] file.asa");
+      return 2;
+    },
+  };
+  let source = match std::fs::read_to_string(path) {
+    Ok(source) => source,
+    Err(_) => {
+      println!("ERROR could not read {}", path);
+      return 2;
+    },
+  };
+  let tree = match program(&source) {
+    Ok((unparsed, tree)) if unparsed.trim().is_empty() => tree,
+    Ok((unparsed, _)) => {
+      println!("ERROR {}: unparsed text {:?}", path, unparsed);
+      return 2;
+    },
+    Err(error) => {
+      println!("ERROR {}: {:?}", path, error);
+      return 2;
+    },
+  };
+  let tree = if optimized { optimize(&tree) } else { tree };
+  if json {
+    match serde_json::to_string(&tree) {
+      Ok(text) => println!("{}", text),
+      Err(error) => {
+        println!("ERROR {}", error);
+        return 2;
+      },
+    }
+  } else {
+    println!("{:#?}", tree);
+  }
+  0
+}
+
+// asalang debug file.asa
+//Runs the file on the tree walker under the console debugger, which pauses before the first statement of main.
+//Type help at the (asa) prompt for the commands.
+fn debug_command(args: &[String]) -> i32 {
+  let path = match args.first() {
+    Some(path) => path,
+    None => {
+      println!("usage: asalang debug file.asa");
+      return 2;
+    },
+  };
+  let source = match std::fs::read_to_string(path) {
+    Ok(source) => source,
+    Err(_) => {
+      println!("ERROR could not read {}", path);
+      return 2;
+    },
+  };
+  let mut runtime = Runtime::new();
+  let input = std::io::BufReader::new(std::io::stdin());
+  runtime.set_debugger(Some(Box::new(Console::new(&source, Box::new(input)))));
+  let result = runtime.load_file(Path::new(path)).and_then(|_| runtime.run_main().map_err(|error| runtime.runtime_error(error)));
+  match result {
+    Ok(value) => {
+      println!("{:?}", value);
+      0
+    },
+    Err(error) => {
+      println!("ERROR {}", error);
+      1
+    },
+  }
+}
 
 fn main() -> Result<(), nom::Err<(&'static str, nom::error::ErrorKind)>> {
+
+  let mut args: Vec<String> = std::env::args().skip(1).collect();
+  if args.first().map(|arg| arg == "lint").unwrap_or(false) {
+    std::process::exit(lint_command(&args[1..]));
+  }
+  if args.first().map(|arg| arg == "parse").unwrap_or(false) {
+    std::process::exit(parse_command(&args[1..]));
+  }
+  if args.first().map(|arg| arg == "fmt").unwrap_or(false) {
+    std::process::exit(fmt_command(&args[1..]));
+  }
+  if args.first().map(|arg| arg == "debug").unwrap_or(false) {
+    std::process::exit(debug_command(&args[1..]));
+  }
+  //asalang dap serves the Debug Adapter Protocol on stdin and stdout for editors, see dap.rs
+  if args.first().map(|arg| arg == "dap").unwrap_or(false) {
+    asalang::dap::serve(Box::new(std::io::BufReader::new(std::io::stdin())), Box::new(std::io::stdout()));
+    return Ok(());
+  }
+  //asalang lsp is the language server for editors, on stdin and stdout, see lsp.rs
+  if args.first().map(|arg| arg == "lsp").unwrap_or(false) {
+    asalang::lsp::serve(Box::new(std::io::BufReader::new(std::io::stdin())), Box::new(std::io::stdout()));
+    return Ok(());
+  }
+
+  //asalang --check file.asa checks the file first and only runs it if there are no resolver errors or type errors,
+  //asalang --vm file.asa runs it on the bytecode VM instead of the tree walker,
+  //asalang --warnings file.asa also shows the warnings of the resolver, errors are always shown
+  let mut type_check = false;
+  let mut warnings = false;
+  let mut engine = Engine::Tree;
+  while let Some(flag) = args.first() {
+    match flag.as_str() {
+      "--check" => type_check = true,
+      "--vm" => engine = Engine::Bytecode,
+      "--warnings" => warnings = true,
+      _ => break,
+    }
+    args.remove(0);
+  }
+
+  //asalang file.asa runs the main function of the file, imports are resolved relative to it
+  if let Some(path) = args.first() {
+    let source = std::fs::read_to_string(path).unwrap_or_default();
+    if let Ok((_, tree)) = program(&source) {
+      //problems that can be seen without running are reported first, on stderr so they do not mix with what the
+      //script prints. Without --check the file still runs, e.g. an undefined call in a branch that is never taken is harmless
+      let diagnostics = resolve(&tree, &[]);
+      for diagnostic in &diagnostics {
+        if warnings || diagnostic.severity == Severity::Error {
+          eprintln!("{}:{}", path, diagnostic.render(&source));
+        }
+      }
+
+      if type_check {
+        if diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error) {
+          std::process::exit(1);
+        }
+        let errors = check(&tree);
+        for error in &errors {
+          println!("TYPE ERROR {}", error);
+        }
+        if !errors.is_empty() {
+          std::process::exit(1);
+        }
+      }
+    }
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    let result = runtime.load_file(Path::new(path)).and_then(|_| runtime.run_main().map_err(|error| runtime.runtime_error(error)));
+    match result {
+      Ok(value) => println!("{:?}", value),
+      Err(error) => println!("ERROR {}", error),
+    }
+    return Ok(());
+  }
   
   let result = program(r#"fn main(){
     let y = 4;
@@ -0,0 +1,149 @@
+use crate::parser::{program, Node};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ModuleError {
+  //the imported file could not be found or read
+  NotFound(PathBuf),
+  //the imported file is not a valid program
+  Parse(PathBuf, String),
+  //the chain of files that import each other, ending with the file that closes the cycle
+  Cycle(Vec<PathBuf>),
+  //running the module (registering functions, evaluating globals or main) failed
+  Runtime(&'static str),
+  //two imported files have the same name, so their functions would share a namespace: the namespace and both files
+  Clash(String, PathBuf, PathBuf),
+  //a call went deeper than the runtime's max depth, these are the calls that were active, outermost first
+  StackOverflow(Vec<String>),
+}
+
+impl ModuleError {
+  // Short description of the error, used where only a static message can be returned (e.g. start_interpreter).
+  pub fn message(&self) -> &'static str {
+    match self {
+      ModuleError::NotFound(_) => "Module not found",
+      ModuleError::Parse(..) => "Module could not be parsed",
+      ModuleError::Cycle(_) => "Import cycle",
+      ModuleError::Clash(..) => "Module namespace clash",
+      ModuleError::Runtime(message) => message,
+      ModuleError::StackOverflow(_) => "Stack overflow",
+    }
+  }
+}
+
+impl fmt::Display for ModuleError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ModuleError::NotFound(path) => write!(f, "module not found: {}", path.display()),
+      ModuleError::Parse(path, message) => write!(f, "could not parse {}: {}", path.display(), message),
+      ModuleError::Cycle(chain) => {
+        let files: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+        write!(f, "import cycle: {}", files.join(" -> "))
+      },
+      ModuleError::Clash(namespace, first, second) => {
+        write!(f, "{} and {} would both be the module {}", first.display(), second.display(), namespace)
+      },
+      ModuleError::Runtime(message) => write!(f, "{}", message),
+      //runs of the same call, like a recursion, are shown once with their count
+      ModuleError::StackOverflow(calls) => {
+        let mut runs: Vec<(&String, usize)> = Vec::new();
+        for call in calls {
+          match runs.last_mut() {
+            Some((name, count)) if *name == call => *count += 1,
+            _ => runs.push((call, 1)),
+          }
+        }
+        let trace: Vec<String> = runs.iter().map(|(name, count)| match count {
+          1 => name.to_string(),
+          count => format!("{} x{}", name, count),
+        }).collect();
+        write!(f, "stack overflow: {}", trace.join(" -> "))
+      },
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct ModuleLoader {
+  //parsed programs by canonical path, so a file imported from several modules is only parsed once
+  cache: HashMap<PathBuf, Rc<Node>>,
+
+  //files that are currently being loaded, in import order
+  //a file that imports one of these closes an import cycle
+  loading: Vec<PathBuf>,
+}
+
+impl Default for ModuleLoader {
+  fn default() -> ModuleLoader {
+    ModuleLoader::new()
+  }
+}
+
+impl ModuleLoader {
+
+  pub fn new() -> ModuleLoader {
+    ModuleLoader {
+      cache: HashMap::new(),
+      loading: Vec::new(),
+    }
+  }
+
+  // Resolves an import path relative to the directory of the importing file.
+  //The result is canonical, so the same file is always found under the same key.
+  pub fn resolve(dir: &Path, import: &str) -> Result<PathBuf, ModuleError> {
+    let file = dir.join(import);
+    fs::canonicalize(&file).map_err(|_| ModuleError::NotFound(file))
+  }
+
+  // The namespace a module's functions are registered under, the file name without its extension (util.asa -> util).
+  pub fn namespace(path: &Path) -> String {
+    match path.file_stem() {
+      Some(stem) => stem.to_string_lossy().to_string(),
+      None => path.to_string_lossy().to_string(),
+    }
+  }
+
+  // Parses the file, or returns the program parsed the last time it was asked for.
+  pub fn parse(&mut self, path: &Path) -> Result<Rc<Node>, ModuleError> {
+    if let Some(tree) = self.cache.get(path) {
+      return Ok(Rc::clone(tree));
+    }
+
+    let source = fs::read_to_string(path).map_err(|_| ModuleError::NotFound(path.to_path_buf()))?;
+    let tree = match program(&source) {
+      Ok((unparsed, tree)) => {
+        if !unparsed.trim().is_empty() {
+          return Err(ModuleError::Parse(path.to_path_buf(), format!("unparsed text {:?}", unparsed)));
+        }
+        Rc::new(tree)
+      },
+      Err(error) => return Err(ModuleError::Parse(path.to_path_buf(), format!("{:?}", error))),
+    };
+
+    self.cache.insert(path.to_path_buf(), Rc::clone(&tree));
+    Ok(tree)
+  }
+
+  // Marks the file as being loaded and returns its program.
+  //Fails if the file is already being loaded further up the import chain.
+  pub fn enter(&mut self, path: &Path) -> Result<Rc<Node>, ModuleError> {
+    if let Some(index) = self.loading.iter().position(|file| file == path) {
+      let mut chain = self.loading[index..].to_vec();
+      chain.push(path.to_path_buf());
+      return Err(ModuleError::Cycle(chain));
+    }
+
+    let tree = self.parse(path)?;
+    self.loading.push(path.to_path_buf());
+    Ok(tree)
+  }
+
+  // Marks the file entered last as done loading.
+  pub fn leave(&mut self) {
+    self.loading.pop();
+  }
+}
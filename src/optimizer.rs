@@ -0,0 +1,355 @@
+use crate::interpreter::{compare, math, Value};
+use crate::parser::{Node, Span};
+use std::collections::{HashMap, HashSet};
+
+// A function whose body is a single return of its parameters and literals, calls to it are replaced by that expression.
+struct Inline {
+  params: Vec<String>,
+  body: Node,
+}
+
+struct Optimizer {
+  //trivial functions of the program by name
+  inlines: HashMap<String, Inline>,
+  //names bound in the function being optimized at the point being optimized, an if block gets a copy
+  scopes: Vec<HashSet<String>>,
+}
+
+fn literal(node: &Node) -> Option<Value> {
+  match node {
+    Node::Expression{children} if children.len() == 1 => literal(&children[0]),
+    Node::Number{value} => Some(Value::Number(*value)),
+    Node::Bool{value} => Some(Value::Bool(*value)),
+    Node::String{value} => Some(Value::String(value.to_string())),
+    Node::Null => Some(Value::Null),
+    _ => None,
+  }
+}
+
+fn from_value(value: Value) -> Node {
+  match value {
+    Value::Number(value) => Node::Number{value},
+    Value::Bool(value) => Node::Bool{value},
+    Value::String(value) => Node::String{value},
+    Value::Null => Node::Null,
+  }
+}
+
+//true when the expression only reads the parameters and literals, so evaluating it has no effects
+fn pure(node: &Node, params: &[String]) -> bool {
+  match node {
+    Node::Identifier{value, ..} => params.contains(value),
+    Node::Expression{..} | Node::MathExpression{..} | Node::ConditionalExpression{..} | Node::CoalesceExpression{..} => {
+      node.children().iter().all(|child| pure(child, params))
+    },
+    _ => literal(node).is_some(),
+  }
+}
+
+//a function can be inlined if it has no annotations to check and its body is one pure return
+//a parameter named like a global const makes the call fail, so that function is left alone
+fn trivial(children: &[Node], constants: &HashSet<String>) -> Option<(String, Inline)> {
+  let name = match &children[0] {
+    Node::Identifier{value, ..} => value.to_string(),
+    _ => return None,
+  };
+  let mut params = Vec::new();
+  let mut body = None;
+  for child in &children[1..] {
+    match child {
+      Node::FunctionArguments{children} => {
+        for param in children {
+          match param.children() {
+            [Node::Identifier{value, ..}] if !params.contains(value) && !constants.contains(value) => params.push(value.to_string()),
+            _ => return None,
+          }
+        }
+      },
+      Node::Statement{children, ..} => match (&children[0], &body) {
+        (Node::FunctionReturn{children}, None) => body = Some(children[0].clone()),
+        _ => return None,
+      },
+      Node::Comment{..} => (),
+      _ => return None,
+    }
+  }
+  match body {
+    Some(body) if pure(&body, &params) => Some((name, Inline{params, body})),
+    _ => None,
+  }
+}
+
+//replaces the parameters in an inlined body with the arguments of the call
+fn substitute(node: &Node, bindings: &HashMap<&str, &Node>) -> Node {
+  match node {
+    Node::Identifier{value, ..} => match bindings.get(value.as_str()) {
+      Some(arg) => (*arg).clone(),
+      None => node.clone(),
+    },
+    _ => {
+      let mut node = node.clone();
+      if let Some(children) = node.children_mut() {
+        for child in children.iter_mut() {
+          *child = substitute(child, bindings);
+        }
+      }
+      node
+    },
+  }
+}
+
+//the value of an if block that only returns an expression, or null for an empty block
+fn block_value(block: &Node) -> Option<Node> {
+  let statements: Vec<&Node> = block.children().iter().filter(|child| !matches!(child, Node::Comment{..})).collect();
+  match statements.as_slice() {
+    [] => Some(Node::Null),
+    [Node::Statement{children, ..}] => match &children[0] {
+      Node::FunctionReturn{children} => Some(children[0].clone()),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+impl Optimizer {
+
+  fn bound(&self, name: &str) -> bool {
+    self.scopes.last().map(|scope| scope.contains(name)).unwrap_or(false)
+  }
+
+  fn bind(&mut self, name: &Node) {
+    if let (Some(scope), Node::Identifier{value, ..}) = (self.scopes.last_mut(), name) {
+      scope.insert(value.to_string());
+    }
+  }
+
+  fn function(&mut self, children: &[Node]) -> Vec<Node> {
+    let mut scope = HashSet::new();
+    for child in children {
+      if let Node::FunctionArguments{children} = child {
+        for param in children {
+          if let Some(Node::Identifier{value, ..}) = param.children().first() {
+            scope.insert(value.to_string());
+          }
+        }
+      }
+    }
+    self.scopes = vec![scope];
+    let mut result: Vec<Node> = children.iter().filter_map(|child| match child {
+      Node::Statement{..} => self.statement(child),
+      _ => Some(child.clone()),
+    }).collect();
+    self.scopes = Vec::new();
+
+    //the runtime rejects a function with nothing in it, so a body whose statements were all left out returns null instead
+    let registered = result.iter().any(|child| match child {
+      Node::Statement{..} | Node::Type{..} => true,
+      Node::FunctionArguments{children} => !children.is_empty(),
+      _ => false,
+    });
+    if !registered && children.iter().any(|child| matches!(child, Node::Statement{..})) {
+      result.push(Node::Statement{children: vec![Node::FunctionReturn{children: vec![Node::Null]}], span: Default::default()});
+    }
+    result
+  }
+
+  //the name is bound after the value, so let x = x + 1 sees the x before it
+  fn define(&mut self, children: &[Node]) -> Vec<Node> {
+    let mut children = children.to_vec();
+    children[1] = self.expression(&children[1]);
+    self.bind(&children[0]);
+    children
+  }
+
+  //None when the statement is left out, a statement that is only a literal does nothing
+  fn statement(&mut self, node: &Node) -> Option<Node> {
+    let (children, span) = match node {
+      Node::Statement{children, span} => (children, *span),
+      _ => return Some(node.clone()),
+    };
+    let child = match &children[0] {
+      Node::VariableDefine{children} => Node::VariableDefine{children: self.define(children)},
+      Node::ConstantDefine{children} => Node::ConstantDefine{children: self.define(children)},
+      Node::FunctionReturn{children} => Node::FunctionReturn{children: vec![self.expression(&children[0])]},
+      //a taken block is kept as it is, a return in it returns from the enclosing block or function
+      Node::IfExpression{children} => match self.condition(children, true) {
+        Node::Null => return None,
+        expression => expression,
+      },
+      Node::ExpressionStatement{children} => {
+        let expression = self.expression(&children[0]);
+        //an inlined call can become an if, it stays an expression so its returns do not leave the function
+        match literal(&expression) {
+          Some(_) => return None,
+          None => Node::ExpressionStatement{children: vec![expression]},
+        }
+      },
+      other => other.clone(),
+    };
+    Some(Node::Statement{children: vec![child], span})
+  }
+
+  fn block(&mut self, node: &Node) -> Node {
+    let scope = self.scopes.last().cloned().unwrap_or_default();
+    self.scopes.push(scope);
+    let children = node.children().iter().filter_map(|child| self.statement(child)).collect();
+    self.scopes.pop();
+    Node::IfStatements{children}
+  }
+
+  fn call(&mut self, name: &str, children: &[Node], span: Span) -> Node {
+    let children: Vec<Node> = children.iter().map(|child| self.expression(child)).collect();
+    let args: Vec<&Node> = children.iter().flat_map(|args| args.children()).collect();
+    //the arguments are copied into the body, so they must not fail or have effects, like literals and bound variables
+    let safe = args.iter().all(|arg| literal(arg).is_some() || match arg {
+      Node::Expression{children} if children.len() == 1 => matches!(&children[0], Node::Identifier{value, ..} if self.bound(value)),
+      Node::Identifier{value, ..} => self.bound(value),
+      _ => false,
+    });
+    let inlined = match self.inlines.get(name) {
+      Some(inline) if safe && inline.params.len() == args.len() => {
+        let bindings = inline.params.iter().map(|param| param.as_str()).zip(args.iter().copied()).collect();
+        Some(substitute(&inline.body, &bindings))
+      },
+      _ => None,
+    };
+    match inlined {
+      Some(body) => self.expression(&body),
+      None => Node::FunctionCall{name: name.to_string(), children, span},
+    }
+  }
+
+  //statement is true for an if whose value is not used
+  fn condition(&mut self, children: &[Node], statement: bool) -> Node {
+    //each branch is a condition and its block, a branch that is always taken becomes the else
+    let mut branches = Vec::new();
+    let mut otherwise = None;
+    let mut all = vec![(Some(&children[0]), &children[1])];
+    for child in &children[2..] {
+      match child {
+        Node::ElseIfExpression{children} => all.push((Some(&children[0]), &children[1])),
+        Node::ElseExpression{children} => all.push((None, &children[0])),
+        _ => (),
+      }
+    }
+    for (condition, block) in all {
+      let condition = condition.map(|condition| self.expression(condition));
+      match condition.as_ref().map(literal) {
+        Some(Some(Value::Bool(false))) => continue,
+        Some(Some(Value::Bool(true))) | None => {
+          otherwise = Some(self.block(block));
+          break;
+        },
+        Some(_) => branches.push((condition.unwrap_or(Node::Null), self.block(block))),
+      }
+    }
+
+    if branches.is_empty() {
+      return match otherwise {
+        None => Node::Null,
+        Some(block) if statement => Node::IfExpression{children: vec![Node::Bool{value: true}, block]},
+        Some(block) => block_value(&block).unwrap_or(Node::IfExpression{children: vec![Node::Bool{value: true}, block]}),
+      };
+    }
+    let mut children = Vec::new();
+    for (index, (condition, block)) in branches.into_iter().enumerate() {
+      if index == 0 {
+        children.push(condition);
+        children.push(block);
+      } else {
+        children.push(Node::ElseIfExpression{children: vec![condition, block]});
+      }
+    }
+    if let Some(block) = otherwise {
+      children.push(Node::ElseExpression{children: vec![block]});
+    }
+    Node::IfExpression{children}
+  }
+
+  fn expression(&mut self, node: &Node) -> Node {
+    match node {
+      Node::MathExpression{name, children} | Node::ConditionalExpression{name, children} => {
+        let lhs = self.expression(&children[0]);
+        let rhs = self.expression(&children[1]);
+        //operations that fail are left for the runtime, so the error still happens when and where it did
+        if let (Some(left), Some(right)) = (literal(&lhs), literal(&rhs)) {
+          let folded = match node {
+            Node::MathExpression{..} => math(name, Ok(left), Ok(right)),
+            _ => compare(name, Ok(left), Ok(right)),
+          };
+          if let Ok(value) = folded {
+            return from_value(value);
+          }
+        }
+        let children = vec![lhs, rhs];
+        match node {
+          Node::MathExpression{..} => Node::MathExpression{name: name.to_string(), children},
+          _ => Node::ConditionalExpression{name: name.to_string(), children},
+        }
+      },
+      Node::CoalesceExpression{children} => {
+        let lhs = self.expression(&children[0]);
+        match literal(&lhs) {
+          Some(Value::Null) => self.expression(&children[1]),
+          Some(_) => lhs,
+          None => Node::CoalesceExpression{children: vec![lhs, self.expression(&children[1])]},
+        }
+      },
+      Node::IfExpression{children} => self.condition(children, false),
+      Node::FunctionCall{name, children, span} => self.call(name, children, *span),
+      _ => {
+        let mut node = node.clone();
+        if let Some(children) = node.children_mut() {
+          for child in children.iter_mut() {
+            *child = self.expression(child);
+          }
+        }
+        node
+      },
+    }
+  }
+}
+
+// Simplifies a parsed program without changing what it does.
+//Folds math and comparisons of literals, e.g. 4 ^ 2 >= 2 * 8 becomes true, drops if branches whose condition
+//is a literal, and replaces calls to functions that only return an expression of their parameters with that
+//expression. Anything that fails at runtime, like a division by zero, is left in place to fail the same way.
+pub fn optimize(program: &Node) -> Node {
+  let children = match program {
+    Node::Program{children} => children,
+    _ => return program.clone(),
+  };
+  let mut optimizer = Optimizer {
+    inlines: HashMap::new(),
+    scopes: Vec::new(),
+  };
+  let mut constants = HashSet::new();
+  for child in children {
+    if let Node::Statement{children, ..} = child {
+      if let Node::ConstantDefine{children} = &children[0] {
+        if let Node::Identifier{value, ..} = &children[0] {
+          constants.insert(value.to_string());
+        }
+      }
+    }
+  }
+  for child in children {
+    if let Node::FunctionDefine{children, ..} = child {
+      //the runtime keeps the last definition
+      match trivial(children, &constants) {
+        Some((name, inline)) => { optimizer.inlines.insert(name, inline); },
+        None => if let Node::Identifier{value, ..} = &children[0] {
+          optimizer.inlines.remove(value);
+        },
+      }
+    }
+  }
+
+  let children = children.iter().map(|child| match child {
+    Node::FunctionDefine{children, span} => Node::FunctionDefine{children: optimizer.function(children), span: *span},
+    //globals are kept even if they do nothing, the runtime decides what is allowed at the top level
+    Node::Statement{..} => optimizer.statement(child).unwrap_or_else(|| child.clone()),
+    _ => child.clone(),
+  }).collect();
+  Node::Program{children}
+}
@@ -4,28 +4,37 @@
 use nom::{
     IResult,
     branch::alt,
-    combinator::opt,
-    multi::{many1, many0},
-    bytes::complete::{tag},
-    character::complete::{alphanumeric1, digit1},
+    combinator::{map, opt, recognize},
+    error::{make_error, ErrorKind},
+    multi::{many1, many0, separated_list1},
+    sequence::{pair, preceded},
+    bytes::complete::{tag, is_not},
+    character::complete::{alphanumeric1, digit1, multispace0, multispace1},
   };
+use serde::{Deserialize, Serialize};
 
   
   // Here are the different node types. You will use these to make your parser and your grammar.
   // You may add other nodes as you see fit, but these are expected by the runtime.
+  //Nodes serialize with serde (e.g. to JSON), the spans may be left out when a tree is read back.
   
-  #[derive(Debug, Clone)]
+  #[derive(Debug, Clone, Serialize, Deserialize)]
   pub enum Node {
     Program { children: Vec<Node> },
-    Statement { children: Vec<Node> },
+    Statement { children: Vec<Node>, #[serde(default)] span: Span },
     FunctionReturn { children: Vec<Node> },
-    FunctionDefine { children: Vec<Node> },
+    FunctionDefine { children: Vec<Node>, #[serde(default)] span: Span },
     FunctionArguments { children: Vec<Node> },
+    //a parameter in a function definition, children are the identifier and an optional type
+    Parameter { children: Vec<Node> },
+    //a type annotation: int, bool, string or null
+    Type { name: String },
     FunctionStatements { children: Vec<Node> },
     Expression { children: Vec<Node> },
     MathExpression {name: String, children: Vec<Node> },
     
     ConditionalExpression {name: String, children: Vec<Node>},
+    CoalesceExpression {children: Vec<Node>},
 
     IfExpression {children: Vec<Node>},
     IfStatements {children: Vec<Node>},
@@ -33,20 +42,161 @@ use nom::{
     ElseExpression{children: Vec<Node>},
 
     MathAdd {children: Vec<Node> },
-    FunctionCall { name: String, children: Vec<Node> },
+    FunctionCall { name: String, children: Vec<Node>, #[serde(default)] span: Span },
+    ExpressionStatement { children: Vec<Node> },
     VariableDefine { children: Vec<Node> },
+    ConstantDefine { children: Vec<Node> },
+    Import { path: String },
+    Use { path: String },
+    //a comment between statements or top-level items, kept for tools like a formatter
+    //trailing is true when the comment is on the same line as the code before it
+    Comment { value: String, trailing: bool },
     Number { value: i32 },
     Bool { value: bool },
-    Identifier { value: String },
+    Identifier { value: String, #[serde(default)] span: Span },
     String { value: String },
     Null,
   }
   
+  // Where the text of a node is in the source, as byte offsets (end is exclusive).
+  #[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+  pub struct Span {
+    pub start: usize,
+    pub end: usize,
+  }
+
+  impl Span {
+    //while parsing, start and end hold the length of the input left at each end of the node
+    //program turns them into offsets once the length of the whole source is known
+    fn remaining(start: &str, end: &str) -> Span {
+      Span{start: start.len(), end: end.len()}
+    }
+
+    // The line and column of the start of the span in source, both counted from 1.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+      let before = &source[..self.start.min(source.len())];
+      let line = before.matches('\n').count() + 1;
+      let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+      (line, column)
+    }
+  }
+
+  impl Node {
+    // The child nodes, empty for literals, identifiers and the other leaves.
+    pub fn children(&self) -> &[Node] {
+      match self {
+        Node::Program{children} | Node::Statement{children, ..} | Node::FunctionReturn{children} |
+        Node::FunctionDefine{children, ..} | Node::FunctionArguments{children} | Node::Parameter{children} |
+        Node::FunctionStatements{children} | Node::Expression{children} | Node::MathExpression{children, ..} |
+        Node::ConditionalExpression{children, ..} | Node::CoalesceExpression{children} | Node::IfExpression{children} |
+        Node::IfStatements{children} | Node::ElseIfExpression{children} | Node::ElseExpression{children} |
+        Node::MathAdd{children} | Node::FunctionCall{children, ..} | Node::ExpressionStatement{children} |
+        Node::VariableDefine{children} | Node::ConstantDefine{children} => children,
+        _ => &[],
+      }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Node>> {
+      match self {
+        Node::Program{children} | Node::Statement{children, ..} | Node::FunctionReturn{children} |
+        Node::FunctionDefine{children, ..} | Node::FunctionArguments{children} | Node::Parameter{children} |
+        Node::FunctionStatements{children} | Node::Expression{children} | Node::MathExpression{children, ..} |
+        Node::ConditionalExpression{children, ..} | Node::CoalesceExpression{children} | Node::IfExpression{children} |
+        Node::IfStatements{children} | Node::ElseIfExpression{children} | Node::ElseExpression{children} |
+        Node::MathAdd{children} | Node::FunctionCall{children, ..} | Node::ExpressionStatement{children} |
+        Node::VariableDefine{children} | Node::ConstantDefine{children} => Some(children),
+        _ => None,
+      }
+    }
+
+    // The span of a statement, function definition, identifier or function call.
+    pub fn span(&self) -> Option<Span> {
+      match self {
+        Node::Statement{span, ..} | Node::FunctionDefine{span, ..} | Node::Identifier{span, ..} | Node::FunctionCall{span, ..} => Some(*span),
+        _ => None,
+      }
+    }
+
+    //turns the spans recorded while parsing into offsets in a source of the given length
+    fn locate(&mut self, length: usize) {
+      if let Node::Statement{span, ..} | Node::FunctionDefine{span, ..} | Node::Identifier{span, ..} | Node::FunctionCall{span, ..} = self {
+        *span = Span{start: length - span.start, end: length - span.end};
+      }
+      if let Some(children) = self.children_mut() {
+        for child in children {
+          child.locate(length);
+        }
+      }
+    }
+  }
+
   // Here is the grammar, for your reference:
+
+  //line_comment = "//" , {?any character except newline?} ;
+  pub fn line_comment(input: &str) -> IResult<&str, &str> {
+    recognize(pair(tag("//"), opt(is_not("\r\n"))))(input)
+  }
+
+  //block_comment = "/*" , {block_comment | ?any character?} , "*/" ;
+  //block comments nest, so /* a /* b */ c */ is a single comment
+  pub fn block_comment(input: &str) -> IResult<&str, &str> {
+    let (mut rest, _) = tag("/*")(input)?;
+    let mut depth = 1;
+    while depth > 0 {
+      if rest.starts_with("/*") {
+        depth += 1;
+        rest = &rest[2..];
+      } else if rest.starts_with("*/") {
+        depth -= 1;
+        rest = &rest[2..];
+      } else {
+        match rest.chars().next() {
+          Some(c) => rest = &rest[c.len_utf8()..],
+          None => return Err(nom::Err::Error(make_error(rest, ErrorKind::TakeUntil))),
+        }
+      }
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+  }
+
+  pub fn comment(input: &str) -> IResult<&str, &str> {
+    alt((line_comment, block_comment))(input)
+  }
+
+  //ws = {whitespace | comment} ;
+  // Skips whitespace (spaces, tabs, newlines, \r\n line endings) and comments.
+  //This is the only combinator that skips whitespace, every other combinator calls it between tokens.
+  pub fn ws(input: &str) -> IResult<&str, &str> {
+    recognize(many0(alt((multispace1, comment))))(input)
+  }
+
+  // Matches a keyword as a whole word, so "let" matches in "let x" but not in "letter".
+  pub fn keyword<'a>(word: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
+    move |input: &'a str| {
+      let (rest, result) = tag(word)(input)?;
+      match rest.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => Err(nom::Err::Error(make_error(input, ErrorKind::Tag))),
+        _ => Ok((rest, result)),
+      }
+    }
+  }
+
+  // A comment standing between statements or top-level items, kept in the tree as a Comment node.
+  pub fn comment_line(input: &str) -> IResult<&str, Node> {
+    let (input, leading) = multispace0(input)?;
+    let (input, value) = comment(input)?;
+    Ok((input, Node::Comment{ value: value.to_string(), trailing: !leading.contains('\n')}))
+  }
   
+  //name = {alnum | "_"} ;
+  pub fn name(input: &str) -> IResult<&str, &str> {
+    recognize(many1(alt((alphanumeric1, tag("_")))))(input)
+  }
+
   pub fn identifier(input: &str) -> IResult<&str, Node> {
-    let (input, result) = alphanumeric1(input)?;              // Consume at least 1 alphanumeric character. The ? automatically unwraps the result if it's okay and bails if it is an error.
-    Ok((input, Node::Identifier{ value: result.to_string()})) // Return the now partially consumed input, as well as a node with the string on it.
+    let start = input;
+    let (input, result) = name(input)?;                       // Consume at least 1 alphanumeric character or _. The ? automatically unwraps the result if it's okay and bails if it is an error.
+    Ok((input, Node::Identifier{ value: result.to_string(), span: Span::remaining(start, input)})) // Return the now partially consumed input, as well as a node with the string on it.
   }
   
   pub fn number(input: &str) -> IResult<&str, Node> {
@@ -56,45 +206,68 @@ use nom::{
   }
   
   pub fn boolean(input: &str) -> IResult<&str, Node> {
-    let (input, result) = alt((tag("true"),tag("false")))(input)?;
+    let (input, result) = alt((keyword("true"),keyword("false")))(input)?;
     let bool_value = if result == "true" {true} else {false};
     Ok((input, Node::Bool{ value: bool_value}))
   }
   
+  pub fn null(input: &str) -> IResult<&str, Node> {
+    let (input, _) = keyword("null")(input)?;
+    Ok((input, Node::Null))
+  }
+
   pub fn string(input: &str) -> IResult<&str, Node> {
     let (input, _) = tag("\"")(input)?;
-    let (input, string) = many1(alt((alphanumeric1,tag(" "))))(input)?;
+    let (input, string) = many0(alt((is_not("\"\\"), escaped_char)))(input)?;
     let (input, _) = tag("\"")(input)?;
     Ok((input, Node::String{ value: string.join("")}))
   }
+
+  //escaped_char = "\\" , ("\"" | "\\" | "n" | "t") ;
+  pub fn escaped_char(input: &str) -> IResult<&str, &str> {
+    let (input, _) = tag("\\")(input)?;
+    alt((
+      map(tag("\""), |_| "\""),
+      map(tag("\\"), |_| "\\"),
+      map(tag("n"), |_| "\n"),
+      map(tag("t"), |_| "\t"),
+    ))(input)
+  }
   
+  //qualified_name = identifier , { "::" , identifier } ;
+  pub fn qualified_name(input: &str) -> IResult<&str, &str> {
+    recognize(separated_list1(tag("::"), name))(input)
+  }
+
   pub fn function_call(input: &str) -> IResult<&str, Node> {
-    let (input, name) = alphanumeric1(input)?;
+    let start = input;
+    let (input, name) = qualified_name(input)?;
     let (input, _) = tag("(")(input)?;
     let (input, mut args) = many0(arguments)(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag(")")(input)?;
-    Ok((input, Node::FunctionCall{name: name.to_string(), children: args}))   
+    Ok((input, Node::FunctionCall{name: name.to_string(), children: args, span: Span::remaining(start, input)}))   
   } 
   
   pub fn parenthetical_expression(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("(")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, args) = alt((conditional_expression,l1))(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, args) = alt((coalesce_expression,l1))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag(")")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     Ok((input, args))
   }
   
   pub fn l4(input: &str) -> IResult<&str, Node> {
-    alt((function_call, boolean, number, identifier, parenthetical_expression))(input)
+    alt((function_call, boolean, null, number, string, identifier, parenthetical_expression))(input)
   }
 
   pub fn l3_infix(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, op) = tag("^")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, args) = l4(input)?;
     Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
   }
@@ -116,9 +289,9 @@ use nom::{
   }
 
   pub fn l2_infix(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, op) = alt((tag("*"),tag("/")))(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, args) = l2(input)?;
     Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
   }
@@ -140,19 +313,19 @@ use nom::{
   }
 
   pub fn l1_infix(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, op) = alt((tag("+"),tag("-")))(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, args) = l2(input)?;
     Ok((input, Node::MathExpression{name: op.to_string(), children: vec![args]}))
   }
 
   pub fn l1(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, mut head) = l2(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, tail) = many0(l1_infix)(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     for n in tail {
       match n {
         Node::MathExpression{name, mut children} => {
@@ -167,7 +340,7 @@ use nom::{
   }
   
   pub fn math_expression(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     l1(input)
     
   }
@@ -190,57 +363,74 @@ use nom::{
   }
   
   pub fn op_infix(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, op) = alt((tag(">="),tag("<="),tag("<"),tag(">"),tag("=="),tag("!=")))(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, args) = math_expression(input)?;
     Ok((input, Node::ConditionalExpression{name: op.to_string(), children: vec![args]}))
   }
   
+  //coalesce_expression = conditional_expression , {"??" , conditional_expression} ;
+  pub fn coalesce_expression(input: &str) -> IResult<&str, Node> {
+    let (input, mut head) = conditional_expression(input)?;
+    let (input, tail) = many0(coalesce_infix)(input)?;
+    for n in tail {
+      head = Node::CoalesceExpression{children: vec![head, n]};
+    }
+    Ok((input, head))
+  }
+
+  pub fn coalesce_infix(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = tag("??")(input)?;
+    let (input, _) = ws(input)?;
+    conditional_expression(input)
+  }
+
   // value = boolean | number | identifier ;
   pub fn value(input: &str) -> IResult<&str, Node> {
-  let (input, _ ) = many0(tag(" "))(input)?;
+  let (input, _ ) = ws(input)?;
   let (input_left, output) = alt((boolean, number, identifier))(input)?;
-  let (input, _ ) = many0(tag(" "))(input)?;
+  let (input, _ ) = ws(input)?;
 
   IResult::Ok((input_left,output))
 }
 
-  //expression = boolean | if_expression | math_expression | function_call | number | string | identifier ;
+  //expression = if_expression | coalesce_expression | math_expression | function_call | boolean | null | number | string | identifier ;
   pub fn expression(input: &str) -> IResult<&str, Node> {
-    let (input, result) = alt((boolean,if_expression, conditional_expression, math_expression, function_call, number, string, identifier))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, result) = alt((if_expression, coalesce_expression, math_expression, function_call, boolean, null, number, string, identifier))(input)?;
     Ok((input, Node::Expression{ children: vec![result]}))   
   }
 
-  //if_expression  = "if" , (conditional_expression | boolean) , "{" , {statement} , "}" , [{ else_if_expression}] , "else" , "{" {statement} "}" ;
+  //if_expression  = "if" , (conditional_expression | boolean) , "{" , {statement} , "}" , [{ else_if_expression}] , [else_expression] ;
   pub fn if_expression(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
-    let (input, _) = tag("if")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("if")(input)?;
+    let (input, _) = ws(input)?;
 
     let (input, if_exp) = alt((conditional_expression,boolean))(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("{")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     //returns a node of IfStatements
     let (input, if_commands) = if_statement(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("}")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     //returns a vec of elseif nodes if any
     let (input, mut else_exp) = many0(else_if_expression)(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
 
 
-    //returns a node of ElseExp
-    let (input, else_commands) = else_expression(input)?;
+    //returns a node of ElseExp, if there is an else
+    let (input, else_commands) = opt(else_expression)(input)?;
 
 
     let mut new_vec = vec![if_exp];
 
     new_vec.push(if_commands);
     new_vec.append(&mut else_exp);
-    new_vec.push(else_commands);
+    if let Some(else_commands) = else_commands {
+      new_vec.push(else_commands);
+    }
 
 
     Ok((input, Node::IfExpression{children: new_vec}))
@@ -248,25 +438,24 @@ use nom::{
 
 
   pub fn if_statement(input: &str) -> IResult<&str, Node> {
-    let (input, statements) = many1(statement)(input)?;
+    let (input, statements) = many0(alt((comment_line, statement)))(input)?;
 
     Ok((input, Node::IfStatements{children: statements}))
   }
 
   //else_if_expression = "else", "if", (conditional_expression | boolean), "{", {statement}, "}" ;
   pub fn else_if_expression(input: &str) -> IResult<&str, Node> {
-    let (input, _) = tag("else")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, _) = tag("if")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("else")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("if")(input)?;
+    let (input, _) = ws(input)?;
     let (input, exp) = alt((conditional_expression,boolean))(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("{")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     let (input, mut commands) = (if_statement)(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("}")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     
     let mut new_vec = vec![exp];
     new_vec.push(commands);
@@ -276,19 +465,18 @@ use nom::{
 
   pub fn else_expression(input: &str) -> IResult<&str, Node> {
 
-    let (input, _) = tag("else")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("else")(input)?;
+    let (input, _) = ws(input)?;
 
     let (input, _) = tag("{")(input)?;
 
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     //returns a vec of statements (else block)
 
     let (input,  else_commands) = if_statement(input)?;
 
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("}")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
 
     Ok((input, Node::ElseExpression{children: vec![else_commands]}))
   
@@ -297,31 +485,84 @@ use nom::{
   }
 
   pub fn statement(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
-    let (input, result) = alt((variable_define, function_return))(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, _) = tag(";")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, _) = many0(tag("\n"))(input)?;
-    Ok((input, Node::Statement{ children: vec![result]}))   
+    let (start, _) = ws(input)?;
+    let (input, result) = alt((variable_define, function_return, if_expression, expression_statement))(start)?;
+    //an if statement ends with its closing brace, the ; is optional
+    let (input, semicolon) = opt(pair(ws, tag(";")))(input)?;
+    if semicolon.is_none() && !matches!(result, Node::IfExpression{..}) {
+      return Err(nom::Err::Error(make_error(input, ErrorKind::Tag)));
+    }
+    Ok((input, Node::Statement{ children: vec![result], span: Span::remaining(start, input)}))   
   }
   
+  //expression_statement = expression ;
+  pub fn expression_statement(input: &str) -> IResult<&str, Node> {
+    let (input, expression) = expression(input)?;
+    Ok((input, Node::ExpressionStatement{ children: vec![expression]}))
+  }
+
   pub fn function_return(input: &str) -> IResult<&str, Node> {
-    let (input, _) = tag("return ")(input)?;
-    let (input, return_value) = alt((function_call, expression, identifier))(input)?;
+    let (input, _) = keyword("return")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, return_value) = expression(input)?;
     Ok((input, Node::FunctionReturn{ children: vec![return_value]}))
   }
   
   pub fn variable_define(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, _) = tag("let ")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("let")(input)?;
+    let (input, _) = ws(input)?;
     let (input, variable) = identifier(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, annotation) = opt(type_annotation)(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("=")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, _) = ws(input)?;
     let (input, expression) = expression(input)?;
-    Ok((input, Node::VariableDefine{ children: vec![variable, expression]}))   
+    //the type goes last so the expression stays the second child
+    let mut children = vec![variable, expression];
+    children.extend(annotation);
+    Ok((input, Node::VariableDefine{children}))   
+  }
+
+  //type_name = "int" | "bool" | "string" | "null" ;
+  pub fn type_name(input: &str) -> IResult<&str, Node> {
+    let (input, name) = alt((keyword("int"), keyword("bool"), keyword("string"), keyword("null")))(input)?;
+    Ok((input, Node::Type{ name: name.to_string()}))
+  }
+
+  //type_annotation = ":" , type_name ;
+  pub fn type_annotation(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = tag(":")(input)?;
+    let (input, _) = ws(input)?;
+    type_name(input)
+  }
+
+  //return_type = "->" , type_name ;
+  pub fn return_type(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = tag("->")(input)?;
+    let (input, _) = ws(input)?;
+    type_name(input)
+  }
+
+  //parameter = identifier , [type_annotation] ;
+  pub fn parameter(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, annotation) = opt(type_annotation)(input)?;
+    let mut children = vec![name];
+    children.extend(annotation);
+    Ok((input, Node::Parameter{children}))
+  }
+
+  //parameters = parameter , {"," , parameter} ;
+  pub fn parameters(input: &str) -> IResult<&str, Node> {
+    let (input, first) = parameter(input)?;
+    let (input, mut others) = many0(preceded(pair(ws, tag(",")), parameter))(input)?;
+    let mut params = vec![first];
+    params.append(&mut others);
+    Ok((input, Node::FunctionArguments{ children: params}))
   }
   
   pub fn arguments(input: &str) -> IResult<&str, Node> {
@@ -333,35 +574,94 @@ use nom::{
   }
   
   pub fn other_arg(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
     let (input, _) = tag(",")(input)?;
     expression(input)
   }
   
   pub fn function_definition(input: &str) -> IResult<&str, Node> {
-    let (input, _) = many0(tag(" "))(input)?;
-    let (input, _) = tag("fn ")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (start, _) = ws(input)?;
+    let (input, _) = keyword("fn")(start)?;
+    let (input, _) = ws(input)?;
     let (input, function_name) = identifier(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("(")(input)?;
-    let (input, mut args) = many0(arguments)(input)?;
+    let (input, args) = opt(parameters)(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag(")")(input)?;
-    let (input, _) = many0(tag(" "))(input)?;
+    let (input, returns) = opt(return_type)(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("{")(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
     
-    let (input, mut statements) = many1(statement)(input)?;
-    let (input, _) = many0(alt((tag(" "),tag("\n"))))(input)?;
+    let (input, mut statements) = many1(alt((comment_line, statement)))(input)?;
+    let (input, _) = ws(input)?;
     let (input, _) = tag("}")(input)?;
-    let (input, _) = many0(alt((tag("\n"),tag(" "))))(input)?;
     let mut children = vec![function_name];
-    children.append(&mut args);
+    children.extend(args);
+    children.extend(returns);
     children.append(&mut statements);
-    Ok((input, Node::FunctionDefine{ children: children }))   
+    Ok((input, Node::FunctionDefine{children, span: Span::remaining(start, input)}))   
   }
   
-  // program = function_definition+ ;
+  pub fn constant_define(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("const")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, variable) = identifier(input)?;
+    let (input, annotation) = opt(type_annotation)(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = tag("=")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, expression) = expression(input)?;
+    let mut children = vec![variable, expression];
+    children.extend(annotation);
+    Ok((input, Node::ConstantDefine{children}))
+  }
+
+  //global_define = (variable_define | constant_define) , ";" ;
+  pub fn global_define(input: &str) -> IResult<&str, Node> {
+    let (start, _) = ws(input)?;
+    let (input, result) = alt((variable_define, constant_define))(start)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = tag(";")(input)?;
+    Ok((input, Node::Statement{ children: vec![result], span: Span::remaining(start, input)}))
+  }
+
+  //import = "import" , "\"" , path , "\"" , ";" ;
+  pub fn import(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("import")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = tag("\"")(input)?;
+    let (input, path) = is_not("\"\n")(input)?;
+    let (input, _) = tag("\"")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = tag(";")(input)?;
+    Ok((input, Node::Import{ path: path.to_string()}))
+  }
+
+  //use = "use" , qualified_name , ";" ;
+  pub fn use_function(input: &str) -> IResult<&str, Node> {
+    let (input, _) = ws(input)?;
+    let (input, _) = keyword("use")(input)?;
+    let (input, _) = ws(input)?;
+    let (input, path) = qualified_name(input)?;
+    let (input, _) = ws(input)?;
+    let (input, _) = tag(";")(input)?;
+    Ok((input, Node::Use{ path: path.to_string()}))
+  }
+
+  // program = {comment | import | use_function | function_definition | global_define}+ ;
   pub fn program(input: &str) -> IResult<&str, Node> {
-    let (input, result) = many1(function_definition)(input)?;
-    Ok((input, Node::Program{ children: result}))
+    let length = input.len();
+    let (input, mut result) = many1(alt((comment_line, import, use_function, function_definition, global_define)))(input)?;
+    let (input, _) = ws(input)?;
+    //there is no code before the first item, so a comment there can not be trailing
+    if let Some(Node::Comment{trailing, ..}) = result.first_mut() {
+      *trailing = false;
+    }
+    let mut tree = Node::Program{ children: result};
+    tree.locate(length);
+    Ok((input, tree))
   }
   
\ No newline at end of file
@@ -0,0 +1,33 @@
+use serde_json::Value as Json;
+use std::io::{BufRead, Write};
+
+// The base protocol of the debug adapter and the language server: each message is a Content-Length header,
+// a blank line and a JSON body.
+
+// The next message, None when the input has ended or is not the protocol.
+pub(crate) fn read(input: &mut dyn BufRead) -> Option<Json> {
+  let mut length = None;
+  loop {
+    let mut header = String::new();
+    if input.read_line(&mut header).ok()? == 0 {
+      return None;
+    }
+    let header = header.trim();
+    if header.is_empty() {
+      break;
+    }
+    if let Some(value) = header.strip_prefix("Content-Length:") {
+      length = value.trim().parse::<usize>().ok();
+    }
+  }
+  let mut body = vec![0; length?];
+  input.read_exact(&mut body).ok()?;
+  serde_json::from_slice(&body).ok()
+}
+
+// Sends a message, a client that has gone away can not be told anything so failed writes are dropped.
+pub(crate) fn write(output: &mut dyn Write, message: &Json) {
+  let body = message.to_string();
+  let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+  let _ = output.flush();
+}
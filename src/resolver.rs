@@ -0,0 +1,274 @@
+use crate::parser::{Node, Span};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+  //the program runs, but probably not the way it was meant to
+  Warning,
+  //the program fails when it gets to this point
+  Error,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Severity::Warning => write!(f, "warning"),
+      Severity::Error => write!(f, "error"),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message: String,
+  //the identifier or call the problem is about, or the statement for code after a return
+  pub span: Span,
+}
+
+impl Diagnostic {
+  // Formats the diagnostic with the line and column it starts at, e.g. "3:3: warning: x is never used".
+  pub fn render(&self, source: &str) -> String {
+    let (line, column) = self.span.line_col(source);
+    format!("{}:{}: {}", line, column, self)
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.severity, self.message)
+  }
+}
+
+// Functions the interpreter provides to every program.
+pub const BUILTINS: [&str; 5] = ["is_null", "assert", "print", "println", "format"];
+
+//a let or const inside a function
+struct Binding {
+  name: String,
+  span: Span,
+  used: bool,
+}
+
+struct Resolver {
+  //functions a call without a module path can reach
+  functions: HashSet<String>,
+  //globals that are defined at the point being resolved
+  globals: HashSet<String>,
+  //every global of the program, a global that is here but not in globals is defined later
+  all_globals: HashSet<String>,
+
+  //bindings of the function being resolved, in the order they are defined
+  bindings: Vec<Binding>,
+  //names in scope and their index in bindings, an if block gets a copy of the enclosing scope like at runtime
+  scopes: Vec<HashMap<String, usize>>,
+  //names defined by a let or const anywhere in the function being resolved
+  declared: HashSet<String>,
+
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+
+  fn report(&mut self, severity: Severity, message: String, span: Span) {
+    self.diagnostics.push(Diagnostic{severity, message, span});
+  }
+
+  fn lookup(&mut self, name: &str, span: Span) {
+    if let Some(index) = self.scopes.last().and_then(|scope| scope.get(name)) {
+      self.bindings[*index].used = true;
+      return;
+    }
+    if self.globals.contains(name) {
+      return;
+    }
+    let message = if self.bindings.iter().any(|binding| binding.name == name) {
+      //defined earlier, but in an if block that has ended
+      format!("{} is not in scope here", name)
+    } else if self.declared.contains(name) || self.all_globals.contains(name) {
+      format!("{} is used before it is defined", name)
+    } else {
+      format!("{} is not defined", name)
+    };
+    self.report(Severity::Error, message, span);
+  }
+
+  fn define(&mut self, name: &str, span: Span, used: bool) {
+    //outside a function there is no scope, globals are tracked by name only
+    if let Some(scope) = self.scopes.last_mut() {
+      self.bindings.push(Binding{name: name.to_string(), span, used});
+      scope.insert(name.to_string(), self.bindings.len() - 1);
+    }
+  }
+
+  // Resolves the statements of a function body or an if block.
+  //A return ends the block, anything after it is reported once and not resolved.
+  fn block(&mut self, statements: &[Node]) {
+    let mut returned = false;
+    for statement in statements {
+      if let Node::Statement{children, span} = statement {
+        if returned {
+          self.report(Severity::Warning, "unreachable statement after return".to_string(), *span);
+          break;
+        }
+        self.statement(statement);
+        returned = matches!(children[0], Node::FunctionReturn{..});
+      }
+    }
+  }
+
+  fn statement(&mut self, node: &Node) {
+    let children = match node {
+      Node::Statement{children, ..} => children,
+      _ => return,
+    };
+    match &children[0] {
+      Node::VariableDefine{children} | Node::ConstantDefine{children} => {
+        //the value is resolved first, so let x = x + 1 uses the x defined before
+        self.expression(&children[1]);
+        if let Node::Identifier{value, span} = &children[0] {
+          self.define(value, *span, value.starts_with('_'));
+        }
+      },
+      other => self.expression(other),
+    }
+  }
+
+  fn expression(&mut self, node: &Node) {
+    match node {
+      Node::Identifier{value, span} => self.lookup(value, *span),
+      Node::FunctionCall{name, children, span} => {
+        //functions of other modules are only known once the imports are loaded
+        if !name.contains("::") && !self.functions.contains(name) {
+          self.report(Severity::Error, format!("function {} is not defined", name), *span);
+        }
+        for child in children {
+          self.expression(child);
+        }
+      },
+      Node::IfStatements{children} => {
+        let scope = self.scopes.last().cloned().unwrap_or_default();
+        self.scopes.push(scope);
+        self.block(children);
+        self.scopes.pop();
+      },
+      _ => {
+        for child in node.children() {
+          self.expression(child);
+        }
+      },
+    }
+  }
+
+  fn function(&mut self, children: &[Node]) {
+    let name = match &children[0] {
+      Node::Identifier{value, ..} => value.to_string(),
+      _ => return,
+    };
+    self.bindings = Vec::new();
+    self.scopes = vec![HashMap::new()];
+    self.declared = HashSet::new();
+    collect_lets(children, &mut self.declared);
+
+    for child in &children[1..] {
+      if let Node::FunctionArguments{children} = child {
+        for param in children {
+          if let Node::Identifier{value, span} = &param.children()[0] {
+            if self.scopes[0].contains_key(value) {
+              self.report(Severity::Error, format!("parameter {} of {} is declared more than once", value, name), *span);
+            }
+            //parameters are part of the function's signature, an unused one is not reported
+            self.define(value, *span, true);
+          }
+        }
+      }
+    }
+    self.block(&children[1..]);
+
+    for binding in std::mem::take(&mut self.bindings) {
+      if !binding.used {
+        self.report(Severity::Warning, format!("{} is never used", binding.name), binding.span);
+      }
+    }
+  }
+}
+
+//the names of every let and const in the nodes, including the ones in if blocks
+fn collect_lets(nodes: &[Node], names: &mut HashSet<String>) {
+  for node in nodes {
+    if let Node::VariableDefine{children} | Node::ConstantDefine{children} = node {
+      if let Node::Identifier{value, ..} = &children[0] {
+        names.insert(value.to_string());
+      }
+    }
+    collect_lets(node.children(), names);
+  }
+}
+
+// Finds mistakes in a parsed program that do not need running it to be seen.
+//Reports calls to functions that do not exist, variables used before they are defined, unused lets,
+//functions and parameters declared twice, and statements after a return. hosts are the names of host
+//functions registered besides the built-in ones. The diagnostics are in source order.
+pub fn resolve(program: &Node, hosts: &[&str]) -> Vec<Diagnostic> {
+  let mut resolver = Resolver {
+    functions: BUILTINS.iter().chain(hosts).map(|name| name.to_string()).collect(),
+    globals: HashSet::new(),
+    all_globals: HashSet::new(),
+    bindings: Vec::new(),
+    scopes: Vec::new(),
+    declared: HashSet::new(),
+    diagnostics: Vec::new(),
+  };
+
+  let children = match program {
+    Node::Program{children} => children,
+    _ => return resolver.diagnostics,
+  };
+
+  let mut defined = HashSet::new();
+  for child in children {
+    match child {
+      Node::FunctionDefine{children, ..} => {
+        if let Node::Identifier{value, span} = &children[0] {
+          //the runtime keeps only the last definition
+          if !defined.insert(value.to_string()) {
+            resolver.report(Severity::Error, format!("function {} is defined more than once", value), *span);
+          }
+          resolver.functions.insert(value.to_string());
+        }
+      },
+      //use brings module::name in as name
+      Node::Use{path} => {
+        if let Some(index) = path.rfind("::") {
+          resolver.functions.insert(path[index + 2..].to_string());
+        }
+      },
+      Node::Statement{..} => collect_lets(std::slice::from_ref(child), &mut resolver.all_globals),
+      _ => (),
+    }
+  }
+
+  //a global initializer only sees the globals before it
+  for child in children {
+    if let Node::Statement{children, ..} = child {
+      resolver.scopes = Vec::new();
+      resolver.statement(child);
+      if let Node::VariableDefine{children} | Node::ConstantDefine{children} = &children[0] {
+        if let Node::Identifier{value, ..} = &children[0] {
+          resolver.globals.insert(value.to_string());
+        }
+      }
+    }
+  }
+
+  for child in children {
+    if let Node::FunctionDefine{children, ..} = child {
+      resolver.function(children);
+    }
+  }
+
+  let mut diagnostics = resolver.diagnostics;
+  diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+  diagnostics
+}
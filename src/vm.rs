@@ -0,0 +1,734 @@
+use crate::interpreter::{compare, is_fatal, math, HostFunction, Runtime, Value, STACK_OVERFLOW};
+use crate::parser::{Node, Span};
+use std::collections::HashMap;
+
+// One instruction of the bytecode, jump targets are indexes into the code of the same function.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+  //pushes constants[index]
+  Constant(usize),
+  Null,
+  //pushes or pops a local slot of the current call
+  Load(usize),
+  Store(usize),
+  Pop,
+  //applies the operator names[index] to the two values on top of the stack
+  Math(usize),
+  Compare(usize),
+  //an error before the matching EndTry leaves a failed operand on the stack and goes on at the target,
+  //Math and Compare turn failed operands into their own errors like the tree walker does
+  Try(usize),
+  EndTry,
+  //pops the condition of an if and jumps to the target when it is false
+  Branch(usize),
+  Jump(usize),
+  //jumps to the target keeping the value on top when it is not null, pops it otherwise
+  Coalesce(usize),
+  //calls functions[index] or hosts[index] with the given number of arguments from the stack
+  Call(usize, usize),
+  CallHost(usize, usize),
+  //like Call followed by Return, but the call replaces the frame of the current function
+  TailCall(usize, usize),
+  //fails with the message when the value on top does not have the type names[index]
+  CheckType(usize, &'static str),
+  Fail(&'static str),
+  Return,
+}
+
+#[derive(Debug)]
+struct Function {
+  //the qualified name, for the call trace of a stack overflow
+  name: String,
+  //type annotations of the parameters, as indexes into names
+  params: Vec<Option<usize>>,
+  returns: Option<usize>,
+  //number of local slots, the parameters come first
+  locals: usize,
+  code: Vec<Op>,
+}
+
+// The functions of a loaded Runtime compiled for the VM.
+//Calls, globals and constants are resolved when compiling, they do not change once main is running.
+#[derive(Debug)]
+pub struct Bytecode {
+  functions: Vec<Function>,
+  constants: Vec<Value>,
+  names: Vec<String>,
+  hosts: Vec<HostFunction>,
+  //the function that calls main
+  entry: usize,
+}
+
+struct Compiler<'a> {
+  runtime: &'a Runtime,
+  //function index by qualified name
+  indexes: HashMap<String, usize>,
+  constants: Vec<Value>,
+  names: Vec<String>,
+  hosts: Vec<HostFunction>,
+
+  //the function being compiled
+  namespace: String,
+  code: Vec<Op>,
+  //local slots by name, an if block gets a copy of the enclosing scope like it gets a copy of the frame at runtime
+  scopes: Vec<HashMap<String, usize>>,
+  locals: usize,
+  //false when the function has a return annotation, its value is checked after the call so it can not be a tail call
+  tail: bool,
+  //the jumps to the end of each if block being compiled, taken by the returns in if statements inside it
+  exits: Vec<Vec<usize>>,
+}
+
+impl<'a> Compiler<'a> {
+
+  fn emit(&mut self, op: Op) -> usize {
+    self.code.push(op);
+    self.code.len() - 1
+  }
+
+  //points the jump at index to the next instruction
+  fn patch(&mut self, index: usize) {
+    let target = self.code.len();
+    match &mut self.code[index] {
+      Op::Try(to) | Op::Branch(to) | Op::Jump(to) | Op::Coalesce(to) => *to = target,
+      _ => (),
+    }
+  }
+
+  fn name(&mut self, name: &str) -> usize {
+    match self.names.iter().position(|known| known == name) {
+      Some(index) => index,
+      None => {
+        self.names.push(name.to_string());
+        self.names.len() - 1
+      },
+    }
+  }
+
+  fn constant(&mut self, value: Value) {
+    self.constants.push(value);
+    let index = self.constants.len() - 1;
+    self.emit(Op::Constant(index));
+  }
+
+  fn qualify(&self, name: &str) -> String {
+    if self.namespace.is_empty() {
+      name.to_string()
+    } else {
+      format!("{}::{}", self.namespace, name)
+    }
+  }
+
+  fn slot(&mut self, name: &str) -> usize {
+    let slot = self.locals;
+    self.locals += 1;
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.insert(name.to_string(), slot);
+    }
+    slot
+  }
+
+  fn function(&mut self, name: &str, body: &[Node]) -> Function {
+    self.namespace = match name.rfind("::") {
+      Some(index) => name[..index].to_string(),
+      None => String::new(),
+    };
+    self.code = Vec::new();
+    self.scopes = vec![HashMap::new()];
+    self.locals = 0;
+    self.exits = Vec::new();
+    self.tail = !body.iter().any(|node| matches!(node, Node::Type{..}));
+
+    let mut params = Vec::new();
+    let mut returns = None;
+    let mut returned = false;
+    //a parameter can not take the name of a global const any more than a let can
+    let constant = body.iter().any(|node| match node {
+      Node::Parameter{children} => matches!(&children[0], Node::Identifier{value, ..} if self.runtime.constants.contains(&self.qualify(value))),
+      Node::Identifier{value, ..} => self.runtime.constants.contains(&self.qualify(value)),
+      _ => false,
+    });
+    if constant {
+      self.emit(Op::Fail("Cannot reassign constant"));
+    }
+    for node in body {
+      match node {
+        Node::Parameter{children} => {
+          if let Node::Identifier{value, ..} = &children[0] {
+            self.slot(value);
+          }
+          params.push(match children.get(1) {
+            Some(Node::Type{name}) => Some(self.name(name)),
+            _ => None,
+          });
+        },
+        Node::Identifier{value, ..} => {
+          self.slot(value);
+          params.push(None);
+        },
+        Node::Type{name} => returns = Some(self.name(name)),
+        Node::Statement{children, ..} => match &children[0] {
+          Node::FunctionReturn{children} => {
+            self.returned(&children[0], self.tail);
+            returned = true;
+          },
+          Node::VariableDefine{children} => self.variable(children),
+          Node::IfExpression{children} => self.condition(children, self.tail, true),
+          Node::ExpressionStatement{children} => {
+            self.expression(&children[0]);
+            self.emit(Op::Pop);
+          },
+          _ => {
+            self.emit(Op::Fail("node is not a variableDefine, functionReturn, ifExpression or expressionStatement"));
+            returned = true;
+          },
+        },
+        Node::FunctionReturn{children} => {
+          self.returned(&children[0], self.tail);
+          returned = true;
+        },
+        _ => {
+          self.emit(Op::Fail("node is not a variableDefine or functionReturn"));
+          returned = true;
+        },
+      }
+      //the statements after a return are never run
+      if returned {
+        break;
+      }
+    }
+    //a function without a return evaluates to null
+    if !returned {
+      self.emit(Op::Null);
+    }
+    self.emit(Op::Return);
+
+    Function {
+      name: name.to_string(),
+      params,
+      returns,
+      locals: self.locals,
+      code: std::mem::take(&mut self.code),
+    }
+  }
+
+  fn variable(&mut self, children: &[Node]) {
+    let name = match &children[0] {
+      Node::Identifier{value, ..} => value,
+      _ => {
+        self.emit(Op::Fail("Not valid ID (variable define)"));
+        return;
+      },
+    };
+    if self.runtime.constants.contains(&self.qualify(name)) {
+      self.emit(Op::Fail("Cannot reassign constant"));
+      return;
+    }
+    self.expression(&children[1]);
+    if let Some(Node::Type{name}) = children.get(2) {
+      let index = self.name(name);
+      self.emit(Op::CheckType(index, "Value does not match its type annotation"));
+    }
+    let slot = self.slot(name);
+    self.emit(Op::Store(slot));
+  }
+
+  //the value of a return, calls to functions of the script are tail calls when tail is true
+  fn returned(&mut self, node: &Node, tail: bool) {
+    match node {
+      _ if !tail => self.expression(node),
+      Node::Expression{children} if !children.is_empty() => self.returned(&children[0], tail),
+      Node::FunctionCall{name, children, ..} => self.call(name, children, tail),
+      Node::IfExpression{children} => self.condition(children, tail, false),
+      _ => self.expression(node),
+    }
+  }
+
+  //the statements of an if block leave the value of the block on the stack, unless the if is a statement:
+  //then a return leaves the enclosing block or function with its value and the block leaves nothing otherwise
+  fn block(&mut self, node: &Node, tail: bool, statement: bool) {
+    let scope = self.scopes.last().cloned().unwrap_or_default();
+    self.scopes.push(scope);
+    if !statement {
+      self.exits.push(Vec::new());
+    }
+
+    let mut returned = false;
+    for child in node.children() {
+      if let Node::Statement{children, ..} = child {
+        match &children[0] {
+          Node::VariableDefine{children} => self.variable(children),
+          //return gives the block its value, the statements after it are not run
+          Node::FunctionReturn{children} => {
+            self.returned(&children[0], tail);
+            if statement && self.exits.is_empty() {
+              self.emit(Op::Return);
+            } else if statement {
+              let exit = self.emit(Op::Jump(0));
+              if let Some(exits) = self.exits.last_mut() {
+                exits.push(exit);
+              }
+            }
+            returned = true;
+            break;
+          },
+          Node::IfExpression{children} => self.condition(children, tail, true),
+          Node::ExpressionStatement{children} => {
+            self.expression(&children[0]);
+            self.emit(Op::Pop);
+          },
+          _ => (),
+        }
+      }
+    }
+    if !returned && !statement {
+      self.emit(Op::Null);
+    }
+    if !statement {
+      for exit in self.exits.pop().unwrap_or_default() {
+        self.patch(exit);
+      }
+    }
+    self.scopes.pop();
+  }
+
+  //an if expression, with its blocks in return position when tail is true, statement is true for an if whose value is not used
+  fn condition(&mut self, children: &[Node], tail: bool, statement: bool) {
+    let mut ends = Vec::new();
+    self.expression(&children[0]);
+    let mut branch = self.emit(Op::Branch(0));
+    self.block(&children[1], tail, statement);
+    ends.push(self.emit(Op::Jump(0)));
+
+    let mut has_else = false;
+    for child in &children[2..] {
+      match child {
+        Node::ElseIfExpression{children} => {
+          self.patch(branch);
+          self.expression(&children[0]);
+          branch = self.emit(Op::Branch(0));
+          self.block(&children[1], tail, statement);
+          ends.push(self.emit(Op::Jump(0)));
+        },
+        Node::ElseExpression{children} => {
+          self.patch(branch);
+          self.block(&children[0], tail, statement);
+          has_else = true;
+          break;
+        },
+        _ => (),
+      }
+    }
+    //no branch was taken and there is no else
+    if !has_else {
+      self.patch(branch);
+      if !statement {
+        self.emit(Op::Null);
+      }
+    }
+    for end in ends {
+      self.patch(end);
+    }
+  }
+
+  //true when evaluating the node can not fail, so it does not need a Try as an operand
+  fn infallible(&self, node: &Node) -> bool {
+    match node {
+      Node::Expression{children} => children.first().map(|child| self.infallible(child)).unwrap_or(false),
+      Node::Number{..} | Node::Bool{..} | Node::String{..} | Node::Null => true,
+      Node::Identifier{value, ..} => {
+        self.scopes.last().map(|scope| scope.contains_key(value)).unwrap_or(false)
+          || self.runtime.globals.contains_key(&self.qualify(value))
+      },
+      _ => false,
+    }
+  }
+
+  fn operand(&mut self, node: &Node) {
+    if self.infallible(node) {
+      self.expression(node);
+      return;
+    }
+    let handler = self.emit(Op::Try(0));
+    self.expression(node);
+    self.emit(Op::EndTry);
+    self.patch(handler);
+  }
+
+  fn call(&mut self, name: &str, children: &[Node], tail: bool) {
+    //resolved like Runtime::resolve_function, then host functions by the name as written
+    let local = self.qualify(name);
+    let target = if self.runtime.functions.contains_key(&local) {
+      local
+    } else {
+      self.runtime.aliases.get(&local).cloned().unwrap_or_else(|| name.to_string())
+    };
+
+    let op = match (self.indexes.get(&target), self.runtime.host_functions.get(name)) {
+      (Some(index), _) if tail => Op::TailCall(*index, 0),
+      (Some(index), _) => Op::Call(*index, 0),
+      (None, Some(host)) => {
+        self.hosts.push(*host);
+        Op::CallHost(self.hosts.len() - 1, 0)
+      },
+      (None, None) => {
+        self.emit(Op::Fail("Undefined function"));
+        return;
+      },
+    };
+
+    let mut count = 0;
+    for args in children {
+      for arg in args.children() {
+        self.expression(arg);
+        count += 1;
+      }
+    }
+    self.emit(match op {
+      Op::Call(index, _) => Op::Call(index, count),
+      Op::TailCall(index, _) => Op::TailCall(index, count),
+      Op::CallHost(index, _) => Op::CallHost(index, count),
+      op => op,
+    });
+  }
+
+  fn expression(&mut self, node: &Node) {
+    match node {
+      Node::Expression{children} => match children.first() {
+        Some(child) => self.expression(child),
+        None => { self.emit(Op::Fail("did not evaluate expression")); },
+      },
+      Node::Number{value} => self.constant(Value::Number(*value)),
+      Node::String{value} => self.constant(Value::String(value.to_string())),
+      Node::Bool{value} => self.constant(Value::Bool(*value)),
+      Node::Null => { self.emit(Op::Null); },
+      Node::Identifier{value, ..} => {
+        //variables in the current frame shadow globals
+        match self.scopes.last().and_then(|scope| scope.get(value)) {
+          Some(slot) => { self.emit(Op::Load(*slot)); },
+          None => match self.runtime.globals.get(&self.qualify(value)) {
+            Some(global) => self.constant(global.clone()),
+            None => { self.emit(Op::Fail("Undefined variable")); },
+          },
+        }
+      },
+      Node::MathExpression{name, children} => {
+        self.operand(&children[0]);
+        self.operand(&children[1]);
+        let index = self.name(name);
+        self.emit(Op::Math(index));
+      },
+      Node::ConditionalExpression{name, children} => {
+        self.operand(&children[0]);
+        self.operand(&children[1]);
+        let index = self.name(name);
+        self.emit(Op::Compare(index));
+      },
+      //the right side is only evaluated when the left side is null
+      Node::CoalesceExpression{children} => {
+        self.expression(&children[0]);
+        let jump = self.emit(Op::Coalesce(0));
+        self.expression(&children[1]);
+        self.patch(jump);
+      },
+      Node::IfExpression{children} => self.condition(children, false, false),
+      Node::FunctionCall{name, children, ..} => self.call(name, children, false),
+      _ => { self.emit(Op::Fail("Unimplemented 2")); },
+    }
+  }
+}
+
+// Compiles every function of the loaded runtime, and an entry function that calls main.
+pub fn compile(runtime: &Runtime) -> Bytecode {
+  let mut names: Vec<&String> = runtime.functions.keys().collect();
+  names.sort();
+  let mut compiler = Compiler {
+    runtime,
+    indexes: names.iter().enumerate().map(|(index, name)| (name.to_string(), index)).collect(),
+    constants: Vec::new(),
+    names: Vec::new(),
+    hosts: Vec::new(),
+    namespace: String::new(),
+    code: Vec::new(),
+    scopes: Vec::new(),
+    locals: 0,
+    tail: true,
+    exits: Vec::new(),
+  };
+
+  let mut functions = Vec::new();
+  for name in names {
+    functions.push(compiler.function(name, &runtime.functions[name]));
+  }
+  let main = Node::FunctionReturn{children: vec![Node::FunctionCall{name: "main".to_string(), children: vec![], span: Span::default()}]};
+  functions.push(compiler.function("", &[main]));
+
+  Bytecode {
+    entry: functions.len() - 1,
+    functions,
+    constants: compiler.constants,
+    names: compiler.names,
+    hosts: compiler.hosts,
+  }
+}
+
+struct Frame {
+  function: usize,
+  pc: usize,
+  //where the locals of the call start in Vm::locals
+  base: usize,
+}
+
+struct Handler {
+  target: usize,
+  //the stack and frame depth to go back to when an error is caught
+  stack: usize,
+  frames: usize,
+}
+
+struct Vm {
+  //failed operands of Math and Compare are kept as errors, everything else is a value
+  stack: Vec<Result<Value, &'static str>>,
+  locals: Vec<Value>,
+  frames: Vec<Frame>,
+  handlers: Vec<Handler>,
+}
+
+impl Vm {
+
+  fn push(&mut self, value: Value) {
+    self.stack.push(Ok(value));
+  }
+
+  fn pop(&mut self) -> Value {
+    match self.stack.pop() {
+      Some(Ok(value)) => value,
+      _ => Value::Null,
+    }
+  }
+
+  //goes on at the innermost Try, or fails the run when there is none or the error is fatal
+  fn raise(&mut self, bytecode: &Bytecode, runtime: &mut Runtime, error: &'static str) -> Result<(), &'static str> {
+    let handler = match self.handlers.pop() {
+      Some(handler) if !is_fatal(error) => handler,
+      _ => return Err(error),
+    };
+    self.frames.truncate(handler.frames);
+    if let Some(frame) = self.frames.last_mut() {
+      frame.pc = handler.target;
+      let len = frame.base + bytecode.functions[frame.function].locals;
+      self.truncate(runtime, len);
+    }
+    self.stack.truncate(handler.stack);
+    self.stack.push(Err(error));
+    Ok(())
+  }
+
+  //takes the arguments of a call off the stack and checks them against the parameters of function
+  fn arguments(&mut self, bytecode: &Bytecode, index: usize, count: usize) -> Result<Vec<Value>, &'static str> {
+    let function = &bytecode.functions[index];
+    let args = self.stack.split_off(self.stack.len() - count);
+    for (position, param) in function.params.iter().enumerate() {
+      let arg = match args.get(position) {
+        Some(Ok(arg)) => arg,
+        _ => return Err("Missing argument"),
+      };
+      if let Some(name) = param {
+        if !arg.has_type(&bytecode.names[*name]) {
+          return Err("Argument does not match its type annotation");
+        }
+      }
+    }
+    Ok(args.into_iter().take(function.params.len()).map(|arg| arg.unwrap_or(Value::Null)).collect())
+  }
+
+  //drops the locals from len on, the memory they held is given back to the runtime
+  fn truncate(&mut self, runtime: &mut Runtime, len: usize) {
+    if len < self.locals.len() {
+      runtime.release(self.locals[len..].iter().map(Value::size).sum());
+      self.locals.truncate(len);
+    }
+  }
+
+  //lays out the locals of a call of function starting at base, the arguments count towards the memory limit
+  fn enter(&mut self, bytecode: &Bytecode, runtime: &mut Runtime, index: usize, base: usize, args: Vec<Value>) -> Result<(), &'static str> {
+    runtime.hold(args.iter().map(Value::size).sum())?;
+    self.locals.extend(args);
+    self.locals.resize(base + bytecode.functions[index].locals, Value::Null);
+    Ok(())
+  }
+
+  //starts a call of function with the arguments on top of the stack
+  fn call(&mut self, bytecode: &Bytecode, runtime: &mut Runtime, index: usize, count: usize) -> Result<(), &'static str> {
+    let args = self.arguments(bytecode, index, count)?;
+    let base = self.locals.len();
+    self.enter(bytecode, runtime, index, base, args)?;
+    self.frames.push(Frame{function: index, pc: 0, base});
+    Ok(())
+  }
+
+  //runs function in the frame of the current call, whose locals are no longer needed
+  fn tail_call(&mut self, bytecode: &Bytecode, runtime: &mut Runtime, index: usize, count: usize) -> Result<(), &'static str> {
+    let args = self.arguments(bytecode, index, count)?;
+    let base = match self.frames.last() {
+      Some(frame) => frame.base,
+      None => return Err("no frame to run"),
+    };
+    self.truncate(runtime, base);
+    self.enter(bytecode, runtime, index, base, args)?;
+    if let Some(frame) = self.frames.last_mut() {
+      frame.function = index;
+      frame.pc = 0;
+    }
+    Ok(())
+  }
+}
+
+impl Bytecode {
+
+  // Runs the entry function, calling main of the runtime the bytecode was compiled from.
+  //The runtime is passed to host functions, so print and the other host functions work like in the tree walker.
+  pub fn run(&self, runtime: &mut Runtime) -> Result<Value, &'static str> {
+    let mut vm = Vm {
+      stack: Vec::new(),
+      locals: vec![Value::Null; self.functions[self.entry].locals],
+      frames: vec![Frame{function: self.entry, pc: 0, base: 0}],
+      handlers: Vec::new(),
+    };
+    let result = self.execute(&mut vm, runtime);
+    //the locals of calls that were still running when the run failed no longer hold memory
+    vm.truncate(runtime, 0);
+    result
+  }
+
+  fn execute(&self, vm: &mut Vm, runtime: &mut Runtime) -> Result<Value, &'static str> {
+    loop {
+      //the host's limits end the run, they are not caught like other errors
+      runtime.step()?;
+      let (op, base) = match vm.frames.last_mut() {
+        Some(frame) => {
+          frame.pc += 1;
+          (self.functions[frame.function].code[frame.pc - 1], frame.base)
+        },
+        None => return Err("no frame to run"),
+      };
+
+      let result = match op {
+        Op::Constant(index) => {
+          vm.push(self.constants[index].clone());
+          Ok(())
+        },
+        Op::Null => {
+          vm.push(Value::Null);
+          Ok(())
+        },
+        Op::Load(slot) => {
+          let value = vm.locals[base + slot].clone();
+          vm.push(value);
+          Ok(())
+        },
+        Op::Store(slot) => {
+          let value = vm.pop();
+          runtime.hold(value.size()).map(|_| {
+            let old = std::mem::replace(&mut vm.locals[base + slot], value);
+            runtime.release(old.size());
+          })
+        },
+        Op::Pop => {
+          vm.stack.pop();
+          Ok(())
+        },
+        Op::Math(name) | Op::Compare(name) => {
+          let rhs = vm.stack.pop().unwrap_or(Ok(Value::Null));
+          let lhs = vm.stack.pop().unwrap_or(Ok(Value::Null));
+          let result = match op {
+            Op::Math(_) => math(&self.names[name], lhs, rhs),
+            _ => compare(&self.names[name], lhs, rhs),
+          };
+          result.map(|value| vm.push(value))
+        },
+        Op::Try(target) => {
+          vm.handlers.push(Handler{target, stack: vm.stack.len(), frames: vm.frames.len()});
+          Ok(())
+        },
+        Op::EndTry => {
+          vm.handlers.pop();
+          Ok(())
+        },
+        Op::Branch(target) => match vm.pop() {
+          Value::Bool(true) => Ok(()),
+          Value::Bool(false) => {
+            if let Some(frame) = vm.frames.last_mut() {
+              frame.pc = target;
+            }
+            Ok(())
+          },
+          _ => Err("no match for if expression"),
+        },
+        Op::Jump(target) => {
+          if let Some(frame) = vm.frames.last_mut() {
+            frame.pc = target;
+          }
+          Ok(())
+        },
+        Op::Coalesce(target) => {
+          match vm.stack.last() {
+            Some(Ok(Value::Null)) => { vm.stack.pop(); },
+            _ => {
+              if let Some(frame) = vm.frames.last_mut() {
+                frame.pc = target;
+              }
+            },
+          }
+          Ok(())
+        },
+        //tail calls replace the frame that makes them, so only calls can go deeper than the limit
+        Op::Call(index, _) if vm.frames.len() >= runtime.max_depth => {
+          runtime.trace = vm.frames.iter()
+            .map(|frame| self.functions[frame.function].name.to_string())
+            .chain(std::iter::once(self.functions[index].name.to_string()))
+            .collect();
+          Err(STACK_OVERFLOW)
+        },
+        Op::Call(index, count) => vm.call(self, runtime, index, count),
+        Op::TailCall(index, count) => vm.tail_call(self, runtime, index, count),
+        Op::CallHost(index, count) => {
+          let args: Vec<Value> = vm.stack.split_off(vm.stack.len() - count).into_iter()
+            .map(|arg| arg.unwrap_or(Value::Null))
+            .collect();
+          //a host function like format can build a value bigger than the memory limit allows
+          (self.hosts[index])(runtime, &args)
+            .and_then(|value| runtime.fits(value.size()).map(|_| vm.push(value)))
+        },
+        Op::CheckType(name, message) => match vm.stack.last() {
+          Some(Ok(value)) if !value.has_type(&self.names[name]) => Err(message),
+          _ => Ok(()),
+        },
+        Op::Fail(message) => Err(message),
+        Op::Return => {
+          let value = vm.pop();
+          let frame = vm.frames.pop().expect("return without a frame");
+          vm.truncate(runtime, frame.base);
+          let checked = match self.functions[frame.function].returns {
+            Some(name) if !value.has_type(&self.names[name]) => Err("Return value does not match its type annotation"),
+            _ => Ok(()),
+          };
+          if vm.frames.is_empty() {
+            return checked.map(|_| value);
+          }
+          checked.map(|_| vm.push(value))
+        },
+      };
+
+      if let Err(error) = result {
+        vm.raise(self, runtime, error)?;
+      }
+    }
+  }
+}
+
+// Compiles the functions of a loaded runtime and runs its main function on the VM.
+pub fn run_main(runtime: &mut Runtime) -> Result<Value, &'static str> {
+  let bytecode = compile(runtime);
+  bytecode.run(runtime)
+}
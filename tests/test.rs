@@ -1,8 +1,20 @@
 extern crate asalang;
 extern crate nom;
+extern crate serde_json;
 
-use asalang::{program, Node, Value, start_interpreter};
+use asalang::{program, Node, Span, Value, Output, Runtime, Engine, start_interpreter, run_file, ModuleError};
 use nom::IResult;
+use asalang::checker::check;
+use asalang::resolver::resolve;
+use asalang::lint::{lint, to_json, LintConfig};
+use asalang::formatter::{format_program, format_source};
+use asalang::optimizer::optimize;
+use asalang::debugger::Console;
+use asalang::dap;
+use asalang::lsp;
+use std::path::Path;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 macro_rules! test {
   ($func:ident, $test:tt, $expected:expr) => (
@@ -12,6 +24,10 @@ macro_rules! test {
         Ok((input, p)) => {
           assert_eq!(input, "");
           assert_eq!(start_interpreter(&p), $expected);
+          //the bytecode VM gives the same result as the tree walker
+          let mut runtime = Runtime::new();
+          runtime.set_engine(Engine::Bytecode);
+          assert_eq!(runtime.start(&p), $expected);
           Ok(())
         },
         Err(e) => Err(format!("{:?}",e)),
@@ -86,3 +102,1505 @@ test!(if_expressions_test_1, r#"fn main(){
   test!(if_expression_test_3, r#" fn main(){ return if 4+3 > 5{ return "I like to code";} 
   else {return "I do not like to code";}; }"#, Ok(Value::String("I like to code".to_string())));
 
+
+//global let and const bindings are visible from every function
+test!(globals_test_1, r#"const limit = 10;
+let base = limit * 2;
+
+fn add(a) {
+  return a + base;
+}
+
+fn main() {
+  return add(limit);
+}"#, Ok(Value::Number(30)));
+
+//globals can be initialized by calling functions defined later in the program
+test!(globals_test_2, r#"let start = five();
+fn five() {
+  return 5;
+}
+fn main() {
+  let x = start + 1;
+  return x;
+}"#, Ok(Value::Number(6)));
+
+//a const can not be redefined inside a function
+test!(globals_test_3, r#"const x = 1;
+fn main() {
+  let x = 2;
+  return x;
+}"#, Err("Cannot reassign constant"));
+
+//a const can not be redefined at the top level either
+test!(globals_test_4, r#"const x = 1;
+let x = 2;
+fn main() {
+  return x;
+}"#, Err("Cannot reassign constant"));
+
+//a parameter can not take the name of a const either
+test!(globals_test_5, r#"const x = 1;
+fn f(x) {
+  return x;
+}
+fn main() {
+  return f(2);
+}"#, Err("Cannot reassign constant"));
+
+//functions of imported files are reachable by namespace or through use, and keep their own globals
+#[test]
+fn modules_test_1() {
+  assert_eq!(run_file(Path::new("tests/modules/main.asa")), Ok(Value::Number(66)));
+}
+// double(4) = 8, square::area(8) = 64, util::offset() = 2
+
+//files that import each other are reported instead of loading forever
+#[test]
+fn modules_test_2() {
+  match run_file(Path::new("tests/modules/cycle_a.asa")) {
+    Err(ModuleError::Cycle(chain)) => {
+      assert_eq!(chain.len(), 3);
+      assert!(chain[0].ends_with("cycle_a.asa"));
+      assert!(chain[2].ends_with("cycle_a.asa"));
+    },
+    other => panic!("expected an import cycle, got {:?}", other),
+  }
+}
+
+test!(modules_test_3, r#"import "tests/modules/missing.asa";
+fn main() {
+  return 1;
+}"#, Err("Module not found"));
+
+//two imported files with the same name would share a namespace, that is reported
+#[test]
+fn modules_test_4() {
+  match run_file(Path::new("tests/modules/clash.asa")) {
+    Err(ModuleError::Clash(namespace, first, second)) => {
+      assert_eq!(namespace, "util");
+      assert!(first.ends_with("modules/util.asa"));
+      assert!(second.ends_with("shapes/util.asa"));
+    },
+    other => panic!("expected a namespace clash, got {:?}", other),
+  }
+}
+
+//line and nested block comments are accepted wherever whitespace is
+test!(comments_test_1, r#"// adds one to its argument
+fn inc(a) { /* the /* nested */ body */
+  let b = a + /* one */ 1; // trailing
+  return b;
+}
+
+/* entry point */
+fn main() {
+  let x = if inc(1) > 1 { // taken
+    return /* two */ inc(1);
+  } else {
+    // never taken
+    return 0;
+  };
+  return x;
+}
+// end of file
+"#, Ok(Value::Number(2)));
+
+//comments between statements are kept as Comment nodes, end-of-line ones are marked trailing
+#[test]
+fn comments_test_2() {
+  let (input, tree) = program("// header\nfn main() {\n  let x = 1; // one\n  // result\n  return x;\n}").unwrap();
+  assert_eq!(input, "");
+  let children = match tree {
+    Node::Program{children} => children,
+    _ => panic!("expected a program"),
+  };
+  match &children[0] {
+    Node::Comment{value, trailing} => {
+      assert_eq!(value, "// header");
+      assert!(!trailing);
+    },
+    other => panic!("expected a comment, got {:?}", other),
+  }
+  let comments: Vec<(String, bool)> = match &children[1] {
+    Node::FunctionDefine{children, ..} => children.iter().filter_map(|child| match child {
+      Node::Comment{value, trailing} => Some((value.to_string(), *trailing)),
+      _ => None,
+    }).collect(),
+    other => panic!("expected a function, got {:?}", other),
+  };
+  assert_eq!(comments, vec![("// one".to_string(), true), ("// result".to_string(), false)]);
+}
+
+//tabs, \r\n line endings and newlines between any two tokens are all whitespace
+test!(whitespace_test_1, "fn add(\r\n\ta ,\r\n\tb\r\n)\r\n{\r\n\tlet sum =\ta\r\n\t\t+ b;\r\n\treturn\r\n\t\tsum;\r\n}\r\n\r\nfn main()\n{\n\treturn add( 1 ,\n 2 );\n}\r\n", Ok(Value::Number(3)));
+
+test!(whitespace_test_2, "fn main()\t{\n\tlet x = if\t3 >\n2\n\t{\n\t\treturn 1;\n\t}\n\telse\n\t{\n\t\treturn 2;\n\t};\n\treturn x;\n}", Ok(Value::Number(1)));
+
+//keywords only match whole words, so identifiers may start with one
+test!(whitespace_test_3, r#"fn main() {
+  let letter = 1;
+  let iffy = letter + 1;
+  let trueish = iffy;
+  return trueish;
+}"#, Ok(Value::Number(2)));
+
+//an if without else evaluates to null when its condition is false
+test!(optional_else_test_1, r#"fn main(){
+  let x = if 1 > 2 {
+    return 1;
+  };
+  return x;
+}"#, Ok(Value::Null));
+
+//if statements need no ; and their branches do not have to return
+test!(optional_else_test_2, r#"fn main(){
+  let x = 5;
+  if x > 2 {
+    let y = 1;
+  }
+  if x > 3 {
+    let z = 2;
+  } else if x > 1 {
+    return 4;
+  } else {
+  };
+  let result = if x > 4 { let w = x * 2; } else { return 0; };
+  return result;
+}"#, Ok(Value::Null));
+
+//the first true else if is taken even when its block evaluates to false
+test!(optional_else_test_3, r#"fn main(){
+  let x = if false {
+    return true;
+  } else if true {
+    return false;
+  } else {
+    return true;
+  };
+  return x;
+}"#, Ok(Value::Bool(false)));
+
+//a condition that is not a bool is a runtime error instead of ending the process
+test!(optional_else_test_4, r#"fn main(){
+  let x = if 1 + 1 { return 1; };
+  return x;
+}"#, Err("no match for if expression"));
+
+//a return in an if statement returns from the function, or gives the enclosing if block its value
+test!(optional_else_test_5, r#"fn f(x) {
+  if x > 5 {
+    return 1;
+  } else if x > 2 {
+    if x > 3 {
+      return 3;
+    }
+  }
+  return 2;
+}
+fn main() {
+  let y = if true {
+    if f(9) == 1 {
+      return 10;
+    }
+    return 20;
+  };
+  return y + f(9) * 100 + f(4) * 1000 + f(3) * 10000;
+}"#, Ok(Value::Number(23110)));
+
+//the value returned from an if statement is checked against the return annotation
+test!(optional_else_test_6, r#"fn f(x) -> int {
+  if x {
+    return "one";
+  }
+  return 1;
+}
+fn main() {
+  return f(true);
+}"#, Err("Return value does not match its type annotation"));
+
+//functions without a return evaluate to null
+test!(null_test_1, r#"fn nothing(a) {
+  let b = a + 1;
+}
+fn main() {
+  return nothing(1);
+}"#, Ok(Value::Null));
+
+//?? only evaluates its right side when the left side is null
+test!(null_test_2, r#"fn find(a) {
+  return if a > 2 { return a; };
+}
+fn main() {
+  let missing = find(1) ?? "none";
+  let found = find(3) ?? missing;
+  return found + 1;
+}"#, Ok(Value::Number(4)));
+
+test!(null_test_3, r#"fn main() {
+  let x = null;
+  let y = is_null(x) == true;
+  let z = is_null(5) != true;
+  return y == z;
+}"#, Ok(Value::Bool(true)));
+
+//null is only equal to null
+test!(null_test_4, r#"fn main() {
+  let x = null ?? null;
+  let eq = x == null;
+  let ne = 1 != null;
+  return if eq == ne { return "both"; };
+}"#, Ok(Value::String("both".to_string())));
+
+//calls can be statements of their own, their value is ignored
+test!(expression_statement_test_1, r#"fn check(a) {
+  assert(a > 1);
+}
+fn main() {
+  check(2);
+  is_null(1);
+  if true {
+    check(3);
+  }
+  return 1;
+}"#, Ok(Value::Number(1)));
+
+test!(expression_statement_test_2, r#"fn main() {
+  assert(1 > 2);
+  return 1;
+}"#, Err("Assertion failed"));
+
+//host functions registered on the runtime are called for their side effects
+static RECORDED: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+fn record(_runtime: &mut Runtime, args: &[Value]) -> Result<Value, &'static str> {
+  match args {
+    [Value::Number(n)] => {
+      RECORDED.fetch_add(*n, std::sync::atomic::Ordering::SeqCst);
+      Ok(Value::Null)
+    },
+    _ => Err("record takes a number"),
+  }
+}
+
+#[test]
+fn expression_statement_test_3() {
+  let (_, tree) = program(r#"fn main() {
+  record(1);
+  record(2 * 3);
+  return record(10);
+}"#).unwrap();
+  let mut runtime = Runtime::new();
+  runtime.register("record", record);
+  assert_eq!(runtime.start(&tree), Ok(Value::Null));
+  assert_eq!(RECORDED.load(std::sync::atomic::Ordering::SeqCst), 17);
+}
+
+//print and println write into the output set on the runtime
+#[test]
+fn output_test_1() {
+  let (_, tree) = program(r#"fn main() {
+  let name = "world";
+  print("hello", name);
+  println("!");
+  println(format("{} + {} = {}, {{ok}} is {}", 1, 2, 1 + 2, null));
+  println("tab\there \"quoted\"");
+  return format("{}", true);
+}"#).unwrap();
+  let mut runtime = Runtime::new();
+  runtime.set_output(Output::Buffer(Vec::new()));
+  assert_eq!(runtime.start(&tree), Ok(Value::String("true".to_string())));
+  assert_eq!(runtime.take_output(), "hello world!\n1 + 2 = 3, {ok} is null\ntab\there \"quoted\"\n");
+  assert_eq!(runtime.take_output(), "");
+}
+
+test!(output_test_2, r#"fn main() {
+  return format("{} and {}", 1);
+}"#, Err("format has more {} than arguments"));
+
+test!(output_test_3, r#"fn main() {
+  return format("{}", 1, 2);
+}"#, Err("format has more arguments than {}"));
+
+fn type_errors(source: &str) -> Vec<String> {
+  let (_, tree) = program(source).unwrap();
+  check(&tree).iter().map(|error| error.to_string()).collect()
+}
+
+//mismatches are found without running the program
+#[test]
+fn checker_test_1() {
+  assert_eq!(type_errors(r#"fn main() {
+  let a = "a" + 1;
+  let b = true < 2;
+  let c = if a > 1 { return 1; } else { return "one"; };
+  return c;
+}"#), vec![
+    "in main: cannot apply + to string and int",
+    "in main: cannot compare bool with int using <",
+    "in main: if branches have different types: int and string",
+  ]);
+}
+
+//types flow through globals, let bindings, function returns and ??
+#[test]
+fn checker_test_2() {
+  assert_eq!(type_errors(r#"const name = "asa";
+fn greeting() {
+  return format("hi {}", name);
+}
+fn count(n) {
+  return if n > 0 { return count(n - 1) + 1; } else { return 0; };
+}
+fn main() {
+  let a = greeting() == count(3);
+  let b = if count(1) { return 1; };
+  let c = (null ?? true) + 1;
+  return name == name;
+}"#), vec![
+    "in main: cannot compare string with int using ==",
+    "in main: if condition is int, not bool",
+    "in main: cannot apply + to bool and int",
+    "in main: cannot compare string with string using ==",
+  ]);
+}
+
+//values only known at runtime, like arguments, are not reported
+#[test]
+fn checker_test_3() {
+  assert_eq!(type_errors(r#"fn add(a, b) {
+  return a + b;
+}
+fn main() {
+  let x = if add(1, 2) > 2 { return add(1, 1); };
+  return x ?? 0;
+}"#), Vec::<String>::new());
+}
+
+//errors are at the call, the name or the statement they are about, so an editor can underline them
+#[test]
+fn checker_test_4() {
+  let source = r#"fn add(a: int, b: int) -> int {
+  return a + b;
+}
+fn name() -> string {
+  return 1;
+}
+fn main() {
+  let x: bool = add(1, "2");
+  let y = if x { return 1; } else { return "one"; };
+  return y;
+}"#;
+  let (_, tree) = program(source).unwrap();
+  let errors: Vec<String> = check(&tree).iter().map(|error| {
+    let (line, column) = error.span.line_col(source);
+    format!("{}:{}: {}", line, column, error.message)
+  }).collect();
+  assert_eq!(errors, vec![
+    "4:4: return value is int, declared string",
+    "8:17: argument 2 of add is string, declared int",
+    "8:7: x is int, declared bool",
+    "9:3: if branches have different types: int and string",
+  ]);
+}
+
+//annotated functions and variables work like unannotated ones when the types match
+test!(annotation_test_1, r#"const limit: int = 10;
+fn add(a: int, b: int) -> int {
+  return a + b;
+}
+fn describe(flag: bool, name) -> string {
+  let text: string = if flag { return name; } else { return "none"; };
+  return text;
+}
+fn main() -> string {
+  let x: int = add(limit, 2);
+  let ok: bool = x > 11;
+  return describe(ok, "big");
+}"#, Ok(Value::String("big".to_string())));
+
+//argument types are enforced when the function is called
+test!(annotation_test_2, r#"fn add(a: int, b: int) -> int {
+  return a + b;
+}
+fn pick(a) {
+  return a;
+}
+fn main() {
+  return add(1, pick(true));
+}"#, Err("Argument does not match its type annotation"));
+
+test!(annotation_test_3, r#"fn answer(a) -> int {
+  return a;
+}
+fn main() {
+  return answer("42");
+}"#, Err("Return value does not match its type annotation"));
+
+test!(annotation_test_4, r#"fn main() {
+  let x: bool = 1 + 1;
+  return x;
+}"#, Err("Value does not match its type annotation"));
+
+//the checker reports annotation mismatches it can see before running
+#[test]
+fn annotation_test_5() {
+  assert_eq!(type_errors(r#"fn add(a: int, b: int) -> int {
+  return a + b;
+}
+fn name() -> string {
+  return 1;
+}
+fn main() {
+  let x: bool = add(1, "2");
+  let y = add(1, 2) + name();
+  return x;
+}"#), vec![
+    "in name: return value is int, declared string",
+    "in main: argument 2 of add is string, declared int",
+    "in main: x is int, declared bool",
+    "in main: cannot apply + to int and string",
+  ]);
+}
+
+fn diagnostics(source: &str) -> Vec<String> {
+  let (_, tree) = program(source).unwrap();
+  resolve(&tree, &["record"]).iter().map(|diagnostic| diagnostic.render(source)).collect()
+}
+
+//undefined names and calls are reported where they are
+#[test]
+fn resolver_test_1() {
+  assert_eq!(diagnostics(r#"const a = b + 1;
+const b = 2;
+fn main() {
+  let x = y;
+  let y = missing(x);
+  record(y);
+  print(z);
+  return x;
+}"#), vec![
+    "1:11: error: b is used before it is defined",
+    "4:11: error: y is used before it is defined",
+    "5:11: error: function missing is not defined",
+    "7:9: error: z is not defined",
+  ]);
+}
+
+//unused lets, names declared twice and code after a return
+#[test]
+fn resolver_test_2() {
+  assert_eq!(diagnostics(r#"fn add(a, a) {
+  let unused = 1;
+  let _ignored = 2;
+  return a;
+  let late = 3;
+  return late;
+}
+fn add(a, b) {
+  return a + b;
+}
+fn main() {
+  let x = 1;
+  let y = if x > 0 {
+    let inner = 2;
+    return x;
+  };
+  return inner + y;
+}"#), vec![
+    "1:11: error: parameter a of add is declared more than once",
+    "2:7: warning: unused is never used",
+    "5:3: warning: unreachable statement after return",
+    "8:4: error: function add is defined more than once",
+    "14:9: warning: inner is never used",
+    "17:10: error: inner is not in scope here",
+  ]);
+}
+
+//a program without problems has no diagnostics, modules and use are not reported
+#[test]
+fn resolver_test_3() {
+  let source = std::fs::read_to_string("tests/modules/main.asa").unwrap();
+  assert_eq!(diagnostics(&source), Vec::<String>::new());
+  assert_eq!(diagnostics(r#"const limit = 10;
+fn main() {
+  let x = limit;
+  if x > 5 {
+    let x = x + 1;
+    record(x);
+  }
+  return x;
+}"#), Vec::<String>::new());
+}
+
+fn lints(source: &str, config: &LintConfig) -> Vec<String> {
+  let (_, tree) = program(source).unwrap();
+  lint(&tree, config).iter().map(|found| found.render(source)).collect()
+}
+
+const LINT_SOURCE: &str = r#"const MAX_SIZE = 100;
+fn checkSize(Value) {
+  let isBig = Value > MAX_SIZE;
+  let grade = if isBig == true {
+    return 1;
+  } else if Value > 50 {
+    return 2;
+  } else if Value > 20 {
+    return 3;
+  } else if Value > 10 {
+    return 4;
+  } else if Value > 0 {
+    return 5;
+  };
+  let always = if true { return 0; };
+  return grade;
+}
+fn main() {
+  return checkSize(MAX_SIZE);
+}"#;
+
+//every rule is on by default
+#[test]
+fn lint_test_1() {
+  assert_eq!(lints(LINT_SOURCE, &LintConfig::default()), vec![
+    "2:1: naming: function checkSize should be snake_case",
+    "2:1: naming: parameter Value should be snake_case",
+    "3:3: naming: variable isBig should be snake_case",
+    "4:3: deep-if: if has 4 else ifs, more than 3",
+    "4:3: redundant-comparison: comparing with a bool literal using == is redundant, use the value itself",
+    "4:3: magic-number: magic number 50, name it with a const",
+    "4:3: magic-number: magic number 20, name it with a const",
+    "4:3: magic-number: magic number 10, name it with a const",
+    "7:5: magic-number: magic number 2, name it with a const",
+    "9:5: magic-number: magic number 3, name it with a const",
+    "11:5: magic-number: magic number 4, name it with a const",
+    "13:5: magic-number: magic number 5, name it with a const",
+    "15:3: constant-condition: the condition is always the same, the if is not needed",
+  ]);
+}
+
+//rules can be turned off and the deep-if limit changed in a config file
+#[test]
+fn lint_test_2() {
+  let config = LintConfig::parse("# only the if rules\nnaming = off\nmagic-number = off\nredundant-comparison = off\nmax-else-if = 4\n").unwrap();
+  assert_eq!(lints(LINT_SOURCE, &config), vec![
+    "15:3: constant-condition: the condition is always the same, the if is not needed",
+  ]);
+  assert_eq!(LintConfig::parse("spelling = on"), Err("line 1: unknown rule spelling".to_string()));
+  assert_eq!(LintConfig::parse("naming = maybe"), Err("line 1: naming must be on or off".to_string()));
+}
+
+//the JSON output has the position of each lint for editors
+#[test]
+fn lint_test_3() {
+  let source = "fn main() {\n  let Total = 1;\n  return Total;\n}";
+  let (_, tree) = program(source).unwrap();
+  assert_eq!(to_json(&lint(&tree, &LintConfig::default()), source),
+    r#"[{"rule":"naming","message":"variable Total should be snake_case","line":2,"column":3,"start":14,"end":28}]"#);
+}
+
+//the formatter normalizes spacing, indentation and braces and keeps comments
+#[test]
+fn formatter_test_1() {
+  let source = r#"// header
+import "util.asa";
+use util::double;
+const   LIMIT:int=10;
+/* about add */
+fn add(a:int,b) -> int { // on the brace
+  let x=a+b*2; // trailing
+  // own line
+  let c = if (x ?? 1) == 2 {return 1;} else if x>1 { return (a+b)*2; } else {};
+  if c == null { print("x\n"); }
+  return x;
+}
+fn main(){return add(1,2);}"#;
+  assert_eq!(format_source(source).unwrap(), r#"// header
+import "util.asa";
+use util::double;
+
+const LIMIT: int = 10;
+
+/* about add */
+fn add(a: int, b) -> int { // on the brace
+  let x = a + b * 2; // trailing
+  // own line
+  let c = if (x ?? 1) == 2 {
+    return 1;
+  } else if x > 1 {
+    return (a + b) * 2;
+  } else {};
+  if c == null {
+    print("x\n");
+  }
+  return x;
+}
+
+fn main() {
+  return add(1, 2);
+}
+"#);
+}
+
+//formatting twice changes nothing, and the formatted program parses to a program that gives the same result
+#[test]
+fn formatter_test_2() {
+  let sources = [
+    std::fs::read_to_string("tests/modules/util.asa").unwrap(),
+    std::fs::read_to_string("tests/modules/main.asa").unwrap(),
+    "fn main() {\n  let a = 7; let b = 3;\n  let x = (a - b) - (a - b - 1) + a * b / 2 + (a * b) / 2;\n  let y = (2 ^ 3) ^ 2 + a / (b * 2);\n  let z = null ?? (a > b) ?? false;\n  let w = if z == true { return 1; } else { return 0; };\n  return x + y + w;\n}".to_string(),
+  ];
+  for source in sources.iter() {
+    let formatted = format_source(source).unwrap();
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+    let (rest, tree) = program(&formatted).unwrap();
+    assert_eq!(rest, "");
+    assert_eq!(format_program(&tree), formatted);
+  }
+  let (_, original) = program(&sources[2]).unwrap();
+  let (_, formatted) = program(&format_source(&sources[2]).unwrap()).unwrap();
+  assert_eq!(start_interpreter(&original), Ok(Value::Number(84)));
+  assert_eq!(start_interpreter(&formatted), Ok(Value::Number(84)));
+}
+
+//comments the layout has no place for make formatting fail instead of being dropped, text in strings is not a comment
+#[test]
+fn formatter_test_3() {
+  let sources = [
+    ("fn main() /* entry */ {\n  return 1;\n}", "/* entry */", 1),
+    ("fn main() {\n  let x = if true {\n    return 1;\n  } // then\n  else {\n    return 2;\n  };\n  return x;\n}", "// then", 4),
+    ("fn main() {\n  return 1 + /* one */ 1;\n}", "/* one */", 2),
+  ];
+  for (source, comment, line) in sources.iter() {
+    assert_eq!(format_source(source), Err(format!("the comment {:?} on line {} would be dropped", comment, line)));
+  }
+  assert_eq!(format_source("fn main() { // kept\n  print(\"/* text */\");\n}").unwrap(), r#"fn main() { // kept
+  print("/* text */");
+}
+"#);
+}
+
+fn math(name: &str, lhs: Node, rhs: Node) -> Node {
+  Node::MathExpression{name: name.to_string(), children: vec![lhs, rhs]}
+}
+
+fn id(name: &str) -> Node {
+  Node::Identifier{value: name.to_string(), span: Span::default()}
+}
+
+fn num(value: i32) -> Node {
+  Node::Number{value}
+}
+
+//built trees only get the parentheses their shape needs, and what is printed parses back to the same source
+#[test]
+fn to_source_test_1() {
+  let cases = vec![
+    (math("-", id("a"), math("-", id("b"), id("c"))), "a - (b - c)"),
+    (math("-", math("-", id("a"), id("b")), id("c")), "a - b - c"),
+    (math("*", math("+", num(1), num(2)), num(3)), "(1 + 2) * 3"),
+    (math("+", num(1), math("*", num(2), num(3))), "1 + 2 * 3"),
+    //* and / group to the right in the parser
+    (math("*", id("a"), math("/", id("b"), id("c"))), "a * b / c"),
+    (math("/", math("*", id("a"), id("b")), id("c")), "(a * b) / c"),
+    (math("^", math("^", id("a"), id("b")), id("c")), "a ^ b ^ c"),
+    (math("^", id("a"), math("^", id("b"), id("c"))), "a ^ (b ^ c)"),
+    (math("*", math("^", id("a"), num(2)), id("b")), "a ^ 2 * b"),
+    (math("*", num(-3), id("x")), "(0 - 3) * x"),
+    (Node::ConditionalExpression{name: "<".to_string(), children: vec![math("+", id("a"), num(1)), id("b")]}, "a + 1 < b"),
+    (Node::CoalesceExpression{children: vec![id("a"), Node::ConditionalExpression{name: "==".to_string(), children: vec![id("b"), Node::Null]}]}, "a ?? b == null"),
+    (Node::FunctionCall{name: "f".to_string(), children: vec![Node::FunctionArguments{children: vec![math("-", num(1), math("-", num(2), num(3))), Node::String{value: "q\"".to_string()}]}], span: Span::default()}, r#"f(1 - (2 - 3), "q\"")"#),
+  ];
+  for (node, source) in cases {
+    assert_eq!(node.to_source(), source);
+    let (rest, parsed) = asalang::parser::expression(source).unwrap();
+    assert_eq!(rest, "");
+    assert_eq!(parsed.to_source(), source);
+  }
+}
+
+//statements and if expressions render over several lines
+#[test]
+fn to_source_test_2() {
+  let (_, tree) = asalang::parser::statement("let x: int = if a>(b+1)*2 {return 1;} else if c {} else {let y=2; return y;};").unwrap();
+  assert_eq!(tree.to_source(), "let x: int = if a > (b + 1) * 2 {\n  return 1;\n} else if c {} else {\n  let y = 2;\n  return y;\n};");
+  let (_, tree) = program("fn add(a: int, b) -> int { return a+b; }").unwrap();
+  assert_eq!(tree.children()[0].to_source(), "fn add(a: int, b) -> int {\n  return a + b;\n}");
+}
+
+//parse trees and values go through JSON unchanged
+#[test]
+fn json_test_1() {
+  let source = "const limit: int = 10;\nfn main() {\n  // note\n  let x = if limit > 5 { return \"big\"; } else { return null; };\n  return x ?? \"none\";\n}\n";
+  let (_, tree) = program(source).unwrap();
+  let json = serde_json::to_string(&tree).unwrap();
+  let read: Node = serde_json::from_str(&json).unwrap();
+  assert_eq!(serde_json::to_string(&read).unwrap(), json);
+  assert_eq!(read.to_source(), tree.to_source());
+  assert_eq!(read.children()[1].span(), tree.children()[1].span());
+  assert_eq!(start_interpreter(&read), Ok(Value::String("big".to_string())));
+
+  for value in [Value::Number(-3), Value::Bool(true), Value::String("a\"b".to_string()), Value::Null] {
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+  }
+}
+
+//other tools can write trees without spans
+#[test]
+fn json_test_2() {
+  let json = r#"{"Program":{"children":[{"FunctionDefine":{"children":[
+    {"Identifier":{"value":"main"}},
+    {"Statement":{"children":[{"FunctionReturn":{"children":[{"Expression":{"children":[
+      {"MathExpression":{"name":"+","children":[{"Number":{"value":1}},{"Number":{"value":2}}]}}
+    ]}}]}}]}}
+  ]}}]}}"#;
+  let tree: Node = serde_json::from_str(json).unwrap();
+  assert_eq!(start_interpreter(&tree), Ok(Value::Number(3)));
+  assert_eq!(serde_json::to_string(&Value::Number(3)).unwrap(), r#"{"Number":3}"#);
+}
+
+//the test! programs also run on the VM, these cover what needs a configured runtime
+#[test]
+fn vm_test_1() {
+  let (_, tree) = program(r#"fn main() {
+  let name = "vm";
+  print("hello", name);
+  println(format("{}", 1 + 2));
+  return format("{}", true);
+}"#).unwrap();
+  let mut runtime = Runtime::new();
+  runtime.set_engine(Engine::Bytecode);
+  runtime.set_output(Output::Buffer(Vec::new()));
+  assert_eq!(runtime.start(&tree), Ok(Value::String("true".to_string())));
+  assert_eq!(runtime.take_output(), "hello vm3\n");
+
+  let mut runtime = Runtime::new();
+  runtime.set_engine(Engine::Bytecode);
+  assert_eq!(runtime.load_file(Path::new("tests/modules/main.asa")), Ok(()));
+  assert_eq!(runtime.run_main(), Ok(Value::Number(66)));
+}
+
+//errors in operands become the operator's error, errors in arguments and returns unwind the calls
+test!(vm_test_2, r#"fn fail(a) {
+  assert(a > 1);
+  return a;
+}
+fn main() {
+  let x = fail(0) + 1;
+  return x;
+}"#, Err("Unimplemeneted math exp"));
+
+test!(vm_test_3, r#"fn fail() {
+  return missing;
+}
+fn pass(a) {
+  return a;
+}
+fn main() {
+  return pass(fail());
+}"#, Err("Undefined variable"));
+
+test!(vm_test_4, r#"fn fail() {
+  return missing;
+}
+fn main() {
+  let ok = if 1 + fail() == 2 { return 1; } else { return 2; };
+  return ok;
+}"#, Err("This Conditional expression is not possible"));
+
+//recursion, shadowing in if blocks and ??
+test!(vm_test_5, r#"const base = 1;
+fn fib(n) {
+  return if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); };
+}
+fn main() {
+  let x = 5;
+  let y = if x > 1 {
+    let x = 10;
+    return x + base;
+  };
+  let z = null ?? fib(x);
+  return y + z + x;
+}"#, Ok(Value::Number(21)));
+
+//cargo test --release fibonacci_benchmark -- --ignored --nocapture times both engines on a recursive program
+#[test]
+#[ignore]
+fn fibonacci_benchmark() {
+  let (_, tree) = program(r#"fn fib(n) {
+  return if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); };
+}
+fn main() {
+  return fib(25);
+}"#).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    let start = std::time::Instant::now();
+    assert_eq!(runtime.start(&tree), Ok(Value::Number(75025)));
+    println!("fib(25) on {:?}: {:?}", engine, start.elapsed());
+  }
+}
+
+//cargo test --release block_benchmark -- --ignored --nocapture times the tree walker entering if blocks
+//in functions with few and with many variables, an if block costs the same whatever the size of its frame
+#[test]
+#[ignore]
+fn block_benchmark() {
+  let time = |locals: usize| {
+    let lets: String = (0..locals).map(|i| format!("  let a{} = {};\n", i, i)).collect();
+    //32 blocks inside each other on every call
+    let blocks = "if n > 0 { ".repeat(32) + &"} ".repeat(32);
+    let source = format!(r#"fn count(n) {{
+{}  {}
+  return if n == 0 {{ return 0; }} else {{ return count(n - 1); }};
+}}
+fn main() {{
+  return count(50000);
+}}"#, lets, blocks);
+    let (_, tree) = program(&source).unwrap();
+    let mut runtime = Runtime::new();
+    runtime.set_engine(Engine::Tree);
+    let start = std::time::Instant::now();
+    assert_eq!(runtime.start(&tree), Ok(Value::Number(0)));
+    let elapsed = start.elapsed();
+    println!("count(50000) with {} locals: {:?}", locals, elapsed);
+    elapsed
+  };
+  let few = time(2);
+  let many = time(64);
+  //the lets make the calls a few times slower, copying the frame into each of the blocks made them 16 times slower
+  assert!(many < few * 8, "{:?} with 64 locals against {:?} with 2", many, few);
+}
+
+fn optimized(source: &str) -> Node {
+  let (_, tree) = program(source).unwrap();
+  let tree = optimize(&tree);
+  //the optimized tree runs the same way as the original on both engines
+  let mut runtime = Runtime::new();
+  runtime.set_engine(Engine::Bytecode);
+  assert_eq!(runtime.start(&tree), start_interpreter(&tree));
+  assert_eq!(start_interpreter(&tree), start_interpreter(&program(source).unwrap().1));
+  tree
+}
+
+//constant math and comparisons are folded, ifs with literal conditions keep only the branch that is taken
+#[test]
+fn optimizer_test_1() {
+  let tree = optimized(r#"fn main() {
+  let a = 4 ^ 2 >= 2 * 8;
+  let b = if 1 > 2 { return 1; } else if a { return 2; } else { return 3; };
+  let c = if false { return 1; } else if true { let d = 4; return d; } else { return 5; };
+  if false { print(1); }
+  return null ?? b + c + (10 - 2) / 4;
+}"#);
+  assert_eq!(tree.to_source(), r#"fn main() {
+  let a = true;
+  let b = if a {
+    return 2;
+  } else {
+    return 3;
+  };
+  let c = if true {
+    let d = 4;
+    return d;
+  };
+  return b + c + 2;
+}
+"#);
+}
+
+//trivial functions are inlined when their arguments are literals or variables, failing operations stay in the tree
+#[test]
+fn optimizer_test_2() {
+  let tree = optimized(r#"fn square(x) {
+  return x * x;
+}
+fn half(x) {
+  return x / 2;
+}
+fn main() {
+  let n = 3;
+  let a = square(n) + square(4) + half(square(2));
+  let b = half(read()) + 1 / 0;
+  return a + b;
+}
+fn read() {
+  let x = 4;
+  return x;
+}"#);
+  let main = tree.children()[2].to_source();
+  assert!(main.contains("let a = n * n + 16 + 2;"), "{}", main);
+  assert!(main.contains("let b = half(read()) + 1 / 0;"), "{}", main);
+  assert_eq!(start_interpreter(&tree), Err("Unimplemeneted math exp"));
+
+  let tree = optimized(r#"fn main() {
+  return 7 / (2 - 2);
+}"#);
+  assert_eq!(tree.to_source(), "fn main() {\n  return 7 / 0;\n}\n");
+  assert_eq!(start_interpreter(&tree), Err("Division by zero"));
+}
+
+//a call that fails because a parameter is named like a const is not inlined away
+#[test]
+fn optimizer_test_3() {
+  let (_, tree) = program(r#"const x = 1;
+fn f(x) {
+  return x;
+}
+fn main() {
+  return f(2);
+}"#).unwrap();
+  assert_eq!(start_interpreter(&optimize(&tree)), Err("Cannot reassign constant"));
+}
+
+//an if statement that is always taken keeps its block, the return in it ends the function
+#[test]
+fn optimizer_test_4() {
+  let tree = optimized(r#"fn main() {
+  if 1 < 2 { return 1; }
+  return 2;
+}"#);
+  assert_eq!(start_interpreter(&tree), Ok(Value::Number(1)));
+  assert_eq!(tree.to_source(), r#"fn main() {
+  if true {
+    return 1;
+  }
+  return 2;
+}
+"#);
+}
+
+//calls in return position, also through the blocks of an if, do not grow the stack
+test!(tail_call_test_1, r#"fn count(n, total) {
+  return if n == 0 { return total; } else { return count(n - 1, total + 1); };
+}
+fn is_even(n) {
+  if n == 0 {
+    return true;
+  }
+  return is_odd(n - 1);
+}
+fn is_odd(n) {
+  return if n == 0 { return false; } else { let m = n - 1; return is_even(m); };
+}
+fn main() {
+  let a = count(200000, 0);
+  let b = is_even(100001);
+  return if b { return 0; } else { return a; };
+}"#, Ok(Value::Number(200000)));
+
+//annotated functions check the value the call returns, errors in a tail call are the same as in any call
+test!(tail_call_test_2, r#"fn down(n: int) -> int {
+  return if n == 0 { return 0; } else { return down(n - 1); };
+}
+fn wrong(n) {
+  return if n == 0 { return down(true); } else { return wrong(n - 1); };
+}
+fn main() {
+  let a = down(50);
+  return wrong(1000);
+}"#, Err("Argument does not match its type annotation"));
+
+//a call returned from an if statement is a tail call as well
+test!(tail_call_test_3, r#"fn count(n, total) {
+  if n > 0 {
+    let next = total + 1;
+    return count(n - 1, next);
+  }
+  return total;
+}
+fn main() {
+  return count(200000, 0);
+}"#, Ok(Value::Number(200000)));
+
+//a call beyond the max depth fails with the calls that were active, tail calls do not count
+#[test]
+fn stack_overflow_test_1() {
+  let (_, tree) = program(r#"fn down(n) {
+  return if n == 0 { return 0; } else { return 1 + down(n - 1); };
+}
+fn tail(n) {
+  return if n == 0 { return 0; } else { return tail(n - 1); };
+}
+fn fib(n) {
+  return if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); };
+}
+fn main() {
+  let a = tail(1000);
+  let b = down(10);
+  let c = down(100);
+  return c;
+}"#).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_max_depth(20);
+    assert_eq!(runtime.start(&tree), Err("Stack overflow"));
+    let mut trace = vec!["main".to_string()];
+    trace.extend(vec!["down".to_string(); 20]);
+    assert_eq!(runtime.stack_trace(), &trace[..]);
+    assert_eq!(runtime.runtime_error("Stack overflow").to_string(), "stack overflow: main -> down x20");
+  }
+
+  //the other operand is not evaluated once the stack has overflowed, so this fails right away
+  let (_, tree) = program(r#"fn fib(n) {
+  return if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); };
+}
+fn main() {
+  return fib(1000);
+}"#).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_max_depth(30);
+    assert_eq!(runtime.start(&tree), Err("Stack overflow"));
+    assert_eq!(runtime.stack_trace().len(), 31);
+  }
+}
+
+//runaway recursion stops at the default limits instead of overflowing the stack of a thread of the default size
+#[test]
+fn stack_overflow_test_2() {
+  let (result, trace) = std::thread::spawn(|| {
+    let (_, tree) = program(r#"fn forever(n) {
+  return 1 + forever(n + 1);
+}
+fn main() {
+  return forever(0);
+}"#).unwrap();
+    let mut runtime = Runtime::new();
+    let result = runtime.start(&tree);
+    (result, runtime.stack_trace().to_vec())
+  }).join().unwrap();
+  assert_eq!(result, Err("Stack overflow"));
+  //main makes its call in return position, so forever replaces it
+  assert!(trace.iter().all(|call| call == "forever"));
+  assert!(trace.len() <= asalang::interpreter::DEFAULT_MAX_DEPTH + 1);
+}
+
+//calls inside nested ifs take more of the host's stack, they still overflow before a thread of the default size does
+#[test]
+fn stack_overflow_test_3() {
+  let result = std::thread::spawn(|| {
+    let (_, tree) = program(r#"fn forever(n) {
+  if n >= 0 {
+    if n >= 0 {
+      if n >= 0 {
+        if n >= 0 {
+          if n >= 0 {
+            let x = 1 + forever(n + 1);
+            return x;
+          }
+        }
+      }
+    }
+  }
+  return 0;
+}
+fn main() {
+  return forever(0);
+}"#).unwrap();
+    let mut runtime = Runtime::new();
+    runtime.start(&tree)
+  }).join().unwrap();
+  assert_eq!(result, Err("Stack overflow"));
+}
+
+//untrusted scripts can be stopped by a step budget, a timeout or from another thread
+const SPIN: &str = r#"fn spin(n) {
+  return spin(n + 1);
+}
+fn main() {
+  let x = 1 + spin(0);
+  return x;
+}"#;
+
+#[test]
+fn limits_test_1() {
+  let (_, tree) = program(SPIN).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_step_budget(Some(10000));
+    assert_eq!(runtime.start(&tree), Err("Step budget exceeded"));
+    assert_eq!(runtime.remaining_steps(), Some(0));
+  }
+
+  //a single power with a huge exponent is one step, it must not loop over the exponent
+  let (_, tree) = program("fn main() {\n  return 1 ^ 2000000000;\n}").unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_step_budget(Some(50));
+    let start = std::time::Instant::now();
+    assert_eq!(runtime.start(&tree), Ok(Value::Number(1)));
+    assert!(start.elapsed() < std::time::Duration::from_millis(500));
+  }
+  let (_, tree) = program("fn main() {\n  return 2 ^ 2000000000;\n}").unwrap();
+  assert_eq!(start_interpreter(&optimize(&tree)), Err("Number overflow"));
+
+  let (_, tree) = program("fn main() {\n  return 1 + 2;\n}").unwrap();
+  let mut runtime = Runtime::new();
+  runtime.set_step_budget(Some(1000));
+  assert_eq!(runtime.start(&tree), Ok(Value::Number(3)));
+  assert!(runtime.remaining_steps().unwrap() < 1000);
+}
+
+#[test]
+fn limits_test_2() {
+  let (_, tree) = program(SPIN).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_timeout(Some(std::time::Duration::from_millis(50)));
+    let start = std::time::Instant::now();
+    assert_eq!(runtime.start(&tree), Err("Timed out"));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    let handle = runtime.interrupt_handle();
+    let interrupter = std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(50));
+      handle.interrupt();
+    });
+    assert_eq!(runtime.start(&tree), Err("Interrupted"));
+    interrupter.join().unwrap();
+  }
+}
+
+const GROW: &str = r#"fn grow(s) {
+  let t = format("{}{}", s, s);
+  return grow(t);
+}
+
+fn main() {
+  return 1 + grow("ab");
+}"#;
+
+#[test]
+fn memory_limit_test_1() {
+  let (_, tree) = program(GROW).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_memory_limit(Some(1 << 20));
+    assert_eq!(runtime.start(&tree), Err("Memory limit exceeded"));
+    assert_eq!(runtime.memory_used(), 0);
+  }
+}
+
+#[test]
+fn memory_limit_test_2() {
+  let (_, tree) = program(r#"let name = "world";
+
+fn greet(who) {
+  let greeting = format("hello {}", who);
+  return greeting;
+}
+
+fn main() {
+  let a = greet(name);
+  let b = greet("there");
+  return b;
+}"#).unwrap();
+  for engine in [Engine::Tree, Engine::Bytecode] {
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_memory_limit(Some(64));
+    assert_eq!(runtime.start(&tree), Ok(Value::String("hello there".to_string())));
+    //only the global is left once main has returned
+    assert_eq!(runtime.memory_used(), 5);
+
+    let mut runtime = Runtime::new();
+    runtime.set_engine(engine);
+    runtime.set_memory_limit(Some(20));
+    assert_eq!(runtime.start(&tree), Err("Memory limit exceeded"));
+  }
+}
+
+const DEBUGGED: &str = r#"fn add(a, b) {
+  let sum = a + b;
+  return sum;
+}
+
+fn main() {
+  let x = 4;
+  let y = add(x, 1);
+  let z = add(y, 2);
+  return z;
+}"#;
+
+//runs DEBUGGED under the console debugger with the commands as its input, returns the result and what was written
+fn debug(commands: &str) -> (Result<Value, &'static str>, String) {
+  let (_, tree) = program(DEBUGGED).unwrap();
+  let mut runtime = Runtime::new();
+  runtime.set_output(Output::Buffer(Vec::new()));
+  let input = std::io::Cursor::new(commands.as_bytes().to_vec());
+  runtime.set_debugger(Some(Box::new(Console::new(DEBUGGED, Box::new(input)))));
+  let result = runtime.start(&tree);
+  (result, runtime.take_output())
+}
+
+#[test]
+fn debugger_test_1() {
+  let (result, output) = debug("b 3\nc\np\nbt\nfinish\nn\ns\ns\n");
+  assert_eq!(result, Ok(Value::Number(7)));
+  assert_eq!(output, "main:7 let x = 4;\n\
+    (asa) breakpoint at line 3\n\
+    (asa) add:3 return sum;\n\
+    (asa) a = Number(4)\nb = Number(1)\nsum = Number(5)\n\
+    (asa) #0 add\n#1 main\n\
+    (asa) main:9 let z = add(y, 2);\n\
+    (asa) add:3 return sum;\n\
+    (asa) main:10 return z;\n\
+    (asa) ");
+}
+
+#[test]
+fn debugger_test_2() {
+  //step goes into calls, next runs through them
+  let (_, output) = debug("n\ns\nn\nn\nq\n");
+  assert_eq!(output, "main:7 let x = 4;\n\
+    (asa) main:8 let y = add(x, 1);\n\
+    (asa) add:2 let sum = a + b;\n\
+    (asa) add:3 return sum;\n\
+    (asa) main:9 let z = add(y, 2);\n\
+    (asa) ");
+
+  let (result, _) = debug("q\n");
+  assert_eq!(result, Err("Interrupted"));
+  //when the input ends the script runs to its end
+  let (result, _) = debug("b 3\n");
+  assert_eq!(result, Ok(Value::Number(7)));
+}
+
+//collects what the DAP server writes, the test reads it once the server is done
+struct Written(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for Written {
+  fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().extend_from_slice(buffer);
+    Ok(buffer.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+//a scripted client: sends the messages in order to a server on stdio-like streams and returns what it sent back
+fn exchange(serve: fn(Box<dyn std::io::BufRead>, Box<dyn std::io::Write>), messages: &[serde_json::Value]) -> Vec<serde_json::Value> {
+  let mut input = String::new();
+  for message in messages {
+    let body = message.to_string();
+    input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+  }
+  let output = Rc::new(RefCell::new(Vec::new()));
+  serve(Box::new(std::io::Cursor::new(input.into_bytes())), Box::new(Written(Rc::clone(&output))));
+
+  let output = String::from_utf8(output.borrow().clone()).unwrap();
+  output.split("Content-Length: ").skip(1).map(|message| {
+    let body = &message[message.find("\r\n\r\n").unwrap() + 4..];
+    serde_json::from_str(body).unwrap()
+  }).collect()
+}
+
+fn dap(requests: &[serde_json::Value]) -> Vec<serde_json::Value> {
+  let requests: Vec<serde_json::Value> = requests.iter().enumerate().map(|(seq, request)| {
+    let mut request = request.clone();
+    request["seq"] = serde_json::json!(seq + 1);
+    request["type"] = serde_json::json!("request");
+    request
+  }).collect();
+  exchange(dap::serve, &requests)
+}
+
+//a message in short: the command of a response and whether it succeeded, or the name of an event
+fn summary(message: &serde_json::Value) -> String {
+  match message["type"].as_str() {
+    Some("response") => format!("{} {}", message["command"].as_str().unwrap(), message["success"]),
+    _ => format!("event {}", message["event"].as_str().unwrap()),
+  }
+}
+
+#[test]
+fn dap_test_1() {
+  use serde_json::json;
+  let messages = dap(&[
+    json!({"command": "initialize", "arguments": {"adapterID": "asalang"}}),
+    json!({"command": "launch", "arguments": {"program": "tests/modules/debugged.asa"}}),
+    json!({"command": "setBreakpoints", "arguments": {"source": {"path": "tests/modules/debugged.asa"}, "breakpoints": [{"line": 3}]}}),
+    json!({"command": "configurationDone"}),
+    json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+    json!({"command": "scopes", "arguments": {"frameId": 0}}),
+    json!({"command": "variables", "arguments": {"variablesReference": 1}}),
+    json!({"command": "next", "arguments": {"threadId": 1}}),
+    json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+    json!({"command": "continue", "arguments": {"threadId": 1}}),
+    json!({"command": "disconnect"}),
+  ]);
+  let summaries: Vec<String> = messages.iter().map(summary).collect();
+  assert_eq!(summaries, vec![
+    "initialize true", "event initialized", "launch true", "setBreakpoints true", "configurationDone true",
+    "event stopped", "stackTrace true", "scopes true", "variables true",
+    "next true", "event stopped", "stackTrace true",
+    "continue true", "event output", "event output", "event output", "event exited", "event terminated", "disconnect true",
+  ]);
+
+  assert_eq!(messages[5]["body"]["reason"], "breakpoint");
+  let frames = &messages[6]["body"]["stackFrames"];
+  assert_eq!(frames[0]["name"], "add");
+  assert_eq!(frames[0]["line"], 3);
+  assert_eq!(frames[1]["name"], "main");
+  assert_eq!(frames[1]["line"], 8);
+  let variables = &messages[8]["body"]["variables"];
+  assert_eq!(variables[2]["name"], "sum");
+  assert_eq!(variables[2]["value"], "5");
+  assert_eq!(variables[2]["type"], "int");
+
+  assert_eq!(messages[10]["body"]["reason"], "step");
+  assert_eq!(messages[11]["body"]["stackFrames"][0]["line"], 9);
+  //println writes the value and the newline separately
+  assert_eq!(messages[13]["body"]["output"], "5");
+  assert_eq!(messages[14]["body"]["output"], "\n");
+  assert_eq!(messages[15]["body"]["output"], "Number(5)\n");
+  assert_eq!(messages[16]["body"]["exitCode"], 0);
+}
+
+#[test]
+fn dap_test_2() {
+  use serde_json::json;
+  //stopOnEntry pauses at the first statement of main, disconnecting there stops the script
+  let messages = dap(&[
+    json!({"command": "initialize"}),
+    json!({"command": "launch", "arguments": {"program": "tests/modules/debugged.asa", "stopOnEntry": true}}),
+    json!({"command": "configurationDone"}),
+    json!({"command": "stepIn", "arguments": {"threadId": 1}}),
+    json!({"command": "stepIn", "arguments": {"threadId": 1}}),
+    json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+    json!({"command": "disconnect"}),
+  ]);
+  let summaries: Vec<String> = messages.iter().map(summary).collect();
+  assert_eq!(summaries, vec![
+    "initialize true", "event initialized", "launch true", "configurationDone true",
+    "event stopped", "stepIn true", "event stopped", "stepIn true", "event stopped", "stackTrace true", "disconnect true",
+  ]);
+  assert_eq!(messages[4]["body"]["reason"], "entry");
+  assert_eq!(messages[9]["body"]["stackFrames"][0]["name"], "add");
+  assert_eq!(messages[9]["body"]["stackFrames"][0]["line"], 2);
+
+  //a breakpoint in another file is not verified and does not stop the program
+  let messages = dap(&[
+    json!({"command": "launch", "arguments": {"program": "tests/modules/debugged.asa"}}),
+    json!({"command": "setBreakpoints", "arguments": {"source": {"path": "tests/modules/util.asa"}, "breakpoints": [{"line": 3}]}}),
+    json!({"command": "configurationDone"}),
+    json!({"command": "disconnect"}),
+  ]);
+  assert_eq!(messages[1]["body"]["breakpoints"][0]["verified"], false);
+  assert!(messages.iter().all(|message| message["event"] != "stopped"));
+
+  let messages = dap(&[
+    json!({"command": "launch", "arguments": {"program": "tests/modules/missing.asa"}}),
+    json!({"command": "continue", "arguments": {"threadId": 1}}),
+  ]);
+  assert_eq!(messages[0]["success"], false);
+  assert_eq!(messages[1]["message"], "the script is not paused");
+}
+
+const EDITED: &str = "let limit = 10;
+
+fn add(a, b: int) {
+  let sum = a + b;
+  return sum;
+}
+
+fn main() {
+  let x = add(1, 2);
+  if x > limit {
+    let big = true;
+  }
+  return x;
+}
+";
+
+//a request of the LSP client about the document at a position
+fn at(id: i64, method: &str, line: i64, character: i64) -> serde_json::Value {
+  serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {
+    "textDocument": {"uri": "file:///edited.asa"},
+    "position": {"line": line, "character": character},
+  }})
+}
+
+#[test]
+fn lsp_test_1() {
+  use serde_json::json;
+  let messages = exchange(lsp::serve, &[
+    json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+    json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+    json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": {"uri": "file:///edited.asa", "languageId": "asalang", "version": 1, "text": EDITED}}}),
+    at(2, "textDocument/definition", 8, 10),
+    at(3, "textDocument/definition", 4, 9),
+    at(4, "textDocument/definition", 9, 9),
+    at(5, "textDocument/hover", 12, 9),
+    at(6, "textDocument/hover", 8, 11),
+    at(7, "textDocument/documentSymbol", 0, 0),
+    at(8, "textDocument/completion", 12, 9),
+    json!({"jsonrpc": "2.0", "id": 9, "method": "shutdown"}),
+    json!({"jsonrpc": "2.0", "method": "exit"}),
+  ]);
+  assert_eq!(messages.len(), 10);
+  assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+
+  let diagnostics = &messages[1]["params"]["diagnostics"];
+  assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+  assert_eq!(diagnostics[0]["message"], "big is never used");
+  assert_eq!(diagnostics[0]["severity"], 2);
+  assert_eq!(diagnostics[0]["range"]["start"], json!({"line": 10, "character": 8}));
+
+  //add, sum and limit are defined on the lines before, at the name
+  assert_eq!(messages[2]["result"]["range"]["start"], json!({"line": 2, "character": 3}));
+  assert_eq!(messages[3]["result"]["range"]["start"], json!({"line": 3, "character": 6}));
+  assert_eq!(messages[4]["result"]["range"]["start"], json!({"line": 0, "character": 4}));
+
+  assert_eq!(messages[5]["result"]["contents"]["value"], "```asalang\nx: int\n```");
+  assert_eq!(messages[6]["result"]["contents"]["value"], "```asalang\nfn add(a, b: int) -> int\n```");
+
+  let symbols = messages[7]["result"].as_array().unwrap();
+  let names: Vec<&str> = symbols.iter().map(|symbol| symbol["name"].as_str().unwrap()).collect();
+  assert_eq!(names, vec!["limit", "add", "main"]);
+  let lets: Vec<&str> = symbols[2]["children"].as_array().unwrap().iter().map(|symbol| symbol["name"].as_str().unwrap()).collect();
+  assert_eq!(lets, vec!["x", "big"]);
+
+  //big is in an if block that has ended and a is a parameter of add
+  let labels: Vec<&str> = messages[8]["result"].as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect();
+  for label in &["x", "limit", "add", "main", "println", "let"] {
+    assert!(labels.contains(label), "{} is not offered", label);
+  }
+  assert!(!labels.contains(&"big") && !labels.contains(&"a"));
+
+  assert_eq!(messages[9]["id"], 9);
+  assert_eq!(messages[9]["result"], serde_json::Value::Null);
+}
+
+#[test]
+fn lsp_test_2() {
+  use serde_json::json;
+  let messy = "fn main(){return 1+2;}";
+  let messages = exchange(lsp::serve, &[
+    json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": {"uri": "file:///edited.asa", "text": messy}}}),
+    json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/formatting", "params": {"textDocument": {"uri": "file:///edited.asa"}, "options": {"tabSize": 2}}}),
+    json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {"textDocument": {"uri": "file:///edited.asa"}, "contentChanges": [{"text": "fn main() {\n  return 1 +;\n}"}]}}),
+    json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/rename", "params": {}}),
+  ]);
+  assert_eq!(messages[0]["params"]["diagnostics"], json!([]));
+  assert_eq!(messages[1]["result"][0]["newText"], format_source(messy).unwrap());
+  assert_eq!(messages[1]["result"][0]["range"]["end"], json!({"line": 0, "character": 22}));
+
+  let diagnostics = &messages[2]["params"]["diagnostics"];
+  assert_eq!(diagnostics[0]["message"], "could not parse this");
+  assert_eq!(diagnostics[0]["severity"], 1);
+  assert_eq!(messages[3]["error"]["code"], -32601);
+}